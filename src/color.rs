@@ -3,46 +3,66 @@
 
 //! Terminal colors.
 //! Provides a macro to color text as well as sturcts to get their
-//! raw ansi codes.
+//! raw ansi codes, and themes for colors picked at runtime.
 
-use std::fmt;
+use std::{env, fmt};
 
 /// Shortcut to produce a String colored with one or more colors.
 /// Example:
 /// ```
+/// # #[macro_use] extern crate shy;
+/// # fn main() {
 ///   let s = color_string!("Red string", Red);
 ///   let x = color_string!("Hyperlink-ish", Blue, Underline);
+/// # assert_eq!("\x1b[91mRed string\x1b[0m", s);
+/// # assert_eq!("\x1b[94;4mHyperlink-ish\x1b[0m", x);
+/// # }
+/// ```
+#[macro_export]
 macro_rules! color_string {
     ($s:expr, $( $color:ident ),+) => {{
         let mut out = String::from("\x1b[");
-        $( out.push_str(crate::color::$color::code()); out.push_str(";"); )+
+        $( out.push_str($crate::color::$color::code()); out.push_str(";"); )+
         out.push('m');
         out.push_str(&$s);
-        out.push_str(crate::color::Reset.as_ref());
+        out.push_str($crate::color::Reset.as_ref());
         out.replace(";m", "m")
     }};
 }
 
 /// Shortcut to produce a color's ANSI escape code. Don't forget to Reset!
 /// ```
+/// # #[macro_use] extern crate shy;
+/// # fn main() {
 ///   let mut o = String::new();
 ///   o.push_str(color!(Blue));
 ///   o.push_str(color!(Underline));
 ///   o.push_str("Hyperlinkish.");
 ///   o.push_str(color!(Reset));
+/// # assert_eq!("\x1b[94m\x1b[4mHyperlinkish.\x1b[0m", o);
+/// # }
+/// ```
+#[macro_export]
 macro_rules! color {
     ($color:ident) => {
-        crate::color::$color.as_ref()
+        $crate::color::$color.as_ref()
     };
 }
 
 /// Create a color:: struct that can be used with format!.
 /// Example:
 /// ```
-///   define_color(Red, 91);
-///   define_color(Reset, 0);
+/// # #[macro_use] extern crate shy;
+/// # fn main() {
+/// # use std::fmt;
+/// # let msg = "oops";
+///   define_color!(Red, 91);
+///   define_color!(Reset, 0);
 ///
-///   println!("{}Error: {}{}", color::Red, msg, color::Reset);
+///   println!("{}Error: {}{}", Red, msg, Reset);
+/// # }
+/// ```
+#[macro_export]
 macro_rules! define_color {
     ($color:ident, $code:literal) => {
         #[allow(missing_docs)]
@@ -101,8 +121,388 @@ define_color!(MagentaBG, 45);
 define_color!(CyanBG, 46);
 define_color!(WhiteBG, 47);
 
+/// Names of the 16 basic colors, in ANSI order.
+const NAMES: &[&str] = &[
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright-black",
+    "bright-red",
+    "bright-green",
+    "bright-yellow",
+    "bright-blue",
+    "bright-magenta",
+    "bright-cyan",
+    "bright-white",
+];
+
+/// A color picked at runtime: one of the 16 basic colors, one of
+/// the 256-color palette, or 24-bit RGB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    /// 0-7 are normal, 8-15 bright.
+    Ansi(u8),
+    Fixed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Parse a name like `red` or `bright-red` (`grey` is
+    /// `bright-black`), a palette number like `208`, or `#ff8700`.
+    pub fn parse(text: &str) -> Result<Color, String> {
+        let name = text.to_lowercase();
+        if name == "grey" || name == "gray" {
+            return Ok(Color::Ansi(8));
+        }
+        if let Some(i) = NAMES.iter().position(|n| *n == name) {
+            return Ok(Color::Ansi(i as u8));
+        }
+        if let Ok(n) = name.parse() {
+            return Ok(Color::Fixed(n));
+        }
+        match name.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.is_ascii() => {
+                let byte = |i| u8::from_str_radix(&hex[i..i + 2], 16);
+                match (byte(0), byte(2), byte(4)) {
+                    (Ok(r), Ok(g), Ok(b)) => Ok(Color::Rgb(r, g, b)),
+                    _ => Err(format!("bad color: {}", text)),
+                }
+            }
+            _ => Err(format!("unknown color: {}", text)),
+        }
+    }
+
+    /// SGR parameters for this as the foreground, or the background.
+    fn code(self, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        match self {
+            Color::Ansi(n) if n < 8 => (base + n as u16).to_string(),
+            Color::Ansi(n) => (base + 60 + (n as u16 - 8)).to_string(),
+            Color::Fixed(n) => format!("{};5;{}", base + 8, n),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::Ansi(n) => write!(f, "{}", NAMES.get(*n as usize).unwrap_or(&"white")),
+            Color::Fixed(n) => write!(f, "{}", n),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+/// How to draw one part of the screen: written like `yellow bold` or
+/// `white on #5f00af`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub underline: bool,
+    /// Swap the foreground and background.
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Parse a foreground color, `on` and a background color, and any
+    /// of `bold`, `underline` and `reverse`, in any order. `none` is
+    /// the terminal's own colors.
+    pub fn parse(text: &str) -> Result<Style, String> {
+        let mut style = Style::default();
+        let mut words = text.split_whitespace();
+        while let Some(word) = words.next() {
+            match word.to_lowercase().as_ref() {
+                "none" => {}
+                "bold" => style.bold = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                "on" => {
+                    let color = words.next().ok_or("missing color after \"on\"")?;
+                    style.bg = Some(Color::parse(color)?);
+                }
+                _ => style.fg = Some(Color::parse(word)?),
+            }
+        }
+        Ok(style)
+    }
+
+    /// The escape code that switches to this style, which is empty if
+    /// there's nothing to switch.
+    pub fn code(&self) -> String {
+        let mut codes = vec![];
+        codes.extend(self.fg.map(|c| c.code(false)));
+        codes.extend(self.bg.map(|c| c.code(true)));
+        for (on, code) in &[(self.bold, "1"), (self.underline, "4"), (self.reverse, "7")] {
+            if *on {
+                codes.push(code.to_string());
+            }
+        }
+        if codes.is_empty() {
+            return String::new();
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+
+    /// `text` in this style, reset afterwards.
+    pub fn paint(&self, text: &str) -> String {
+        let code = self.code();
+        if code.is_empty() {
+            return text.to_string();
+        }
+        format!("{}{}{}", code, text, Reset)
+    }
+
+    /// The same style without colors. Reverse video stands in for a
+    /// background color, so status bars still look like bars.
+    pub fn without_color(self) -> Style {
+        Style {
+            fg: None,
+            bg: None,
+            reverse: self.reverse || self.bg.is_some(),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut words = vec![];
+        words.extend(self.fg.map(|c| c.to_string()));
+        if let Some(bg) = self.bg {
+            words.push(format!("on {}", bg));
+        }
+        for (on, word) in &[
+            (self.bold, "bold"),
+            (self.underline, "underline"),
+            (self.reverse, "reverse"),
+        ] {
+            if *on {
+                words.push(word.to_string());
+            }
+        }
+        if words.is_empty() {
+            words.push("none".into());
+        }
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+/// The built-in themes.
+pub const PRESETS: &[&str] = &["default", "high-contrast", "light"];
+
+/// The style of every part of the screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// The preset this started as.
+    pub name: String,
+    /// The selected host, and its matched letters.
+    pub selected: Style,
+    pub selected_match: Style,
+    /// Every other host, and its matched letters.
+    pub host: Style,
+    pub matched: Style,
+    /// The nav mode status bar, and messages.
+    pub status: Style,
+    /// The search prompt while it's empty, matching, and not.
+    pub prompt: Style,
+    pub prompt_found: Style,
+    pub prompt_missed: Style,
+    /// Form errors and the delete prompt.
+    pub error: Style,
+    /// Headings in the detail pane and form.
+    pub title: Style,
+    /// Setting names in the detail pane.
+    pub label: Style,
+    /// Less important things, like where a setting came from.
+    pub dim: Style,
+    /// The mark next to favorites.
+    pub pinned: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::preset("default").unwrap()
+    }
+}
+
+impl Theme {
+    /// One of the `PRESETS`.
+    pub fn preset(name: &str) -> Option<Theme> {
+        let styles = match name {
+            "default" => [
+                "bright-yellow bold",
+                "underline",
+                "bright-white",
+                "bright-cyan bold",
+                "bright-yellow on magenta bold",
+                "black on white",
+                "black on green",
+                "bright-white on red",
+                "bright-white on red bold",
+                "bright-yellow bold",
+                "bright-cyan",
+                "grey",
+                "bright-magenta bold",
+            ],
+            "high-contrast" => [
+                "black on bright-yellow bold",
+                "underline",
+                "bright-white bold",
+                "bright-yellow bold underline",
+                "black on bright-white bold",
+                "black on bright-white",
+                "black on bright-green bold",
+                "bright-white on red bold",
+                "bright-white on red bold",
+                "bright-white bold underline",
+                "bright-yellow",
+                "white",
+                "bright-magenta bold",
+            ],
+            "light" => [
+                "blue bold",
+                "underline",
+                "black",
+                "magenta bold",
+                "bright-white on blue bold",
+                "bright-white on black",
+                "bright-white on green",
+                "bright-white on red",
+                "bright-white on red bold",
+                "blue bold",
+                "cyan",
+                "bright-black",
+                "magenta bold",
+            ],
+            _ => return None,
+        };
+
+        let style = |i: usize| Style::parse(styles[i]).expect("bad preset style");
+        Some(Theme {
+            name: name.to_string(),
+            selected: style(0),
+            selected_match: style(1),
+            host: style(2),
+            matched: style(3),
+            status: style(4),
+            prompt: style(5),
+            prompt_found: style(6),
+            prompt_missed: style(7),
+            error: style(8),
+            title: style(9),
+            label: style(10),
+            dim: style(11),
+            pinned: style(12),
+        })
+    }
+
+    /// Every style, by the name used for it in the config file.
+    pub fn roles(&self) -> Vec<(&'static str, Style)> {
+        vec![
+            ("selected", self.selected),
+            ("selected_match", self.selected_match),
+            ("host", self.host),
+            ("match", self.matched),
+            ("status", self.status),
+            ("prompt", self.prompt),
+            ("prompt_found", self.prompt_found),
+            ("prompt_missed", self.prompt_missed),
+            ("error", self.error),
+            ("title", self.title),
+            ("label", self.label),
+            ("dim", self.dim),
+            ("pinned", self.pinned),
+        ]
+    }
+
+    /// Change the style named `role`.
+    pub fn set(&mut self, role: &str, style: Style) -> Result<(), String> {
+        let slot = match role {
+            "selected" => &mut self.selected,
+            "selected_match" => &mut self.selected_match,
+            "host" => &mut self.host,
+            "match" => &mut self.matched,
+            "status" => &mut self.status,
+            "prompt" => &mut self.prompt,
+            "prompt_found" => &mut self.prompt_found,
+            "prompt_missed" => &mut self.prompt_missed,
+            "error" => &mut self.error,
+            "title" => &mut self.title,
+            "label" => &mut self.label,
+            "dim" => &mut self.dim,
+            "pinned" => &mut self.pinned,
+            _ => return Err(format!("unknown color role: {}", role)),
+        };
+        *slot = style;
+        Ok(())
+    }
+
+    /// The same theme without colors, for `NO_COLOR`.
+    pub fn without_color(&self) -> Theme {
+        let mut theme = self.clone();
+        for (role, style) in self.roles() {
+            let _ = theme.set(role, style.without_color());
+        }
+        theme
+    }
+}
+
+/// Has the user asked for no colors? See https://no-color.org.
+pub fn no_color() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styles() {
+        let style = Style::parse("bright-yellow on magenta bold").unwrap();
+        assert_eq!("\x1b[93;45;1m", style.code());
+        assert_eq!(
+            "\x1b[38;5;208;48;2;0;95;175m",
+            Style::parse("208 on #005fAF").unwrap().code()
+        );
+        assert_eq!(
+            "\x1b[90;4mx\x1b[0m",
+            Style::parse("grey underline").unwrap().paint("x")
+        );
+        assert_eq!("x", Style::parse("none").unwrap().paint("x"));
+        assert_eq!("\x1b[1;7m", style.without_color().code());
+
+        for text in &["bright-black on 17 reverse", "#ffffff", "none"] {
+            assert_eq!(*text, Style::parse(text).unwrap().to_string());
+        }
+        assert!(Style::parse("red on").is_err());
+        assert!(Style::parse("mauve").is_err());
+        assert!(Style::parse("256").is_err());
+        assert!(Style::parse("#12345g").is_err());
+    }
+
+    #[test]
+    fn test_themes() {
+        for name in PRESETS {
+            assert_eq!(Some(*name), Theme::preset(name).map(|t| t.name).as_deref());
+        }
+        let mut theme = Theme::default();
+        assert!(theme.set("match", Style::parse("red").unwrap()).is_ok());
+        assert!(theme.set("matches", Style::default()).is_err());
+        assert!(theme
+            .without_color()
+            .roles()
+            .iter()
+            .all(|(_, style)| style.fg.is_none() && style.bg.is_none()));
+    }
+
     #[test]
     fn test_colors() {
         assert_eq!(color_string!("Error", Red), "\x1b[91mError\x1b[0m");
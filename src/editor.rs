@@ -0,0 +1,295 @@
+//! A one-line, readline-ish text editor for the search prompt. The
+//! cursor always sits on a grapheme boundary, so combining marks and
+//! emoji are edited as one character.
+
+use {
+    termion::event::Key, unicode_segmentation::UnicodeSegmentation, unicode_width::UnicodeWidthStr,
+};
+
+/// Something to do to the line, named after readline's commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edit {
+    BackwardChar,
+    ForwardChar,
+    BeginningOfLine,
+    EndOfLine,
+    BackwardWord,
+    ForwardWord,
+    BackwardDeleteChar,
+    DeleteChar,
+    UnixWordRubout,
+    BackwardKillWord,
+    KillWord,
+    UnixLineDiscard,
+    KillLine,
+}
+
+/// Every edit, by the name used for it in the config file.
+pub const EDITS: &[(&str, Edit)] = &[
+    ("backward-char", Edit::BackwardChar),
+    ("forward-char", Edit::ForwardChar),
+    ("beginning-of-line", Edit::BeginningOfLine),
+    ("end-of-line", Edit::EndOfLine),
+    ("backward-word", Edit::BackwardWord),
+    ("forward-word", Edit::ForwardWord),
+    ("backward-delete-char", Edit::BackwardDeleteChar),
+    ("delete-char", Edit::DeleteChar),
+    ("unix-word-rubout", Edit::UnixWordRubout),
+    ("backward-kill-word", Edit::BackwardKillWord),
+    ("kill-word", Edit::KillWord),
+    ("unix-line-discard", Edit::UnixLineDiscard),
+    ("kill-line", Edit::KillLine),
+];
+
+/// The readline keys for each edit.
+pub const DEFAULT_KEYS: &[(Key, Edit)] = &[
+    (Key::Left, Edit::BackwardChar),
+    (Key::Ctrl('b'), Edit::BackwardChar),
+    (Key::Right, Edit::ForwardChar),
+    (Key::Ctrl('f'), Edit::ForwardChar),
+    (Key::Home, Edit::BeginningOfLine),
+    (Key::Ctrl('a'), Edit::BeginningOfLine),
+    (Key::End, Edit::EndOfLine),
+    (Key::Ctrl('e'), Edit::EndOfLine),
+    (Key::Alt('b'), Edit::BackwardWord),
+    (Key::Alt('f'), Edit::ForwardWord),
+    (Key::Backspace, Edit::BackwardDeleteChar),
+    (Key::Ctrl('h'), Edit::BackwardDeleteChar),
+    (Key::Delete, Edit::DeleteChar),
+    (Key::Ctrl('d'), Edit::DeleteChar),
+    (Key::Ctrl('w'), Edit::UnixWordRubout),
+    (Key::Alt('\x7f'), Edit::BackwardKillWord),
+    (Key::Alt('d'), Edit::KillWord),
+    (Key::Ctrl('u'), Edit::UnixLineDiscard),
+    (Key::Ctrl('k'), Edit::KillLine),
+];
+
+/// The text being edited and where the cursor is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineEditor {
+    text: String,
+    /// Byte offset into `text`.
+    cursor: usize,
+}
+
+impl LineEditor {
+    /// An empty line.
+    pub fn new() -> LineEditor {
+        LineEditor::default()
+    }
+
+    /// The whole line.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Empty the line.
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Byte offset of the cursor.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// How many terminal columns are left of the cursor.
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.cursor].width()
+    }
+
+    /// Handle a key press with the default keys. Returns false if it
+    /// isn't an editing key.
+    pub fn update(&mut self, key: Key) -> bool {
+        if let Some((_, edit)) = DEFAULT_KEYS.iter().find(|(k, _)| *k == key) {
+            self.edit(*edit);
+            return true;
+        }
+        match key {
+            Key::Char(c) => self.insert(c),
+            _ => false,
+        }
+    }
+
+    /// Type a character at the cursor. Newlines and tabs aren't
+    /// allowed, so this returns false for them.
+    pub fn insert(&mut self, c: char) -> bool {
+        if c == '\n' || c == '\t' {
+            return false;
+        }
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        true
+    }
+
+    /// Move the cursor or delete something.
+    pub fn edit(&mut self, edit: Edit) {
+        match edit {
+            Edit::BackwardChar => self.cursor = self.prev_grapheme(),
+            Edit::ForwardChar => self.cursor = self.next_grapheme(),
+            Edit::BeginningOfLine => self.cursor = 0,
+            Edit::EndOfLine => self.cursor = self.text.len(),
+            Edit::BackwardWord => self.cursor = self.prev_word(),
+            Edit::ForwardWord => self.cursor = self.next_word(),
+            Edit::BackwardDeleteChar => self.delete_back(self.prev_grapheme()),
+            Edit::DeleteChar => self.delete_forward(self.next_grapheme()),
+            Edit::UnixWordRubout => self.delete_back(self.prev_whitespace_word()),
+            Edit::BackwardKillWord => self.delete_back(self.prev_word()),
+            Edit::KillWord => self.delete_forward(self.next_word()),
+            Edit::UnixLineDiscard => self.delete_back(0),
+            Edit::KillLine => self.delete_forward(self.text.len()),
+        }
+    }
+
+    /// Delete from `start` up to the cursor.
+    fn delete_back(&mut self, start: usize) {
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete from the cursor up to `end`.
+    fn delete_forward(&mut self, end: usize) {
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Start of the grapheme before the cursor.
+    fn prev_grapheme(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// End of the grapheme after the cursor.
+    fn next_grapheme(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+
+    /// Start of the word before the cursor, like readline's Alt-B.
+    /// Words are runs of letters and digits.
+    fn prev_word(&self) -> usize {
+        self.prev_boundary(is_word)
+    }
+
+    /// Start of the whitespace-separated word before the cursor, like
+    /// readline's Ctrl-W.
+    fn prev_whitespace_word(&self) -> usize {
+        self.prev_boundary(|g| !g.chars().all(char::is_whitespace))
+    }
+
+    /// Walk back over graphemes that aren't `in_word`, then the ones
+    /// that are.
+    fn prev_boundary(&self, in_word: impl Fn(&str) -> bool) -> usize {
+        let mut start = self.cursor;
+        let mut seen_word = false;
+        for (i, g) in self.text[..self.cursor].grapheme_indices(true).rev() {
+            if in_word(g) {
+                seen_word = true;
+            } else if seen_word {
+                break;
+            }
+            start = i;
+        }
+        start
+    }
+
+    /// End of the word after the cursor, like readline's Alt-F.
+    fn next_word(&self) -> usize {
+        let mut end = self.cursor;
+        let mut seen_word = false;
+        for g in self.text[self.cursor..].graphemes(true) {
+            if is_word(g) {
+                seen_word = true;
+            } else if seen_word {
+                break;
+            }
+            end += g.len();
+        }
+        end
+    }
+}
+
+/// Is this grapheme part of a word?
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> LineEditor {
+        let mut line = LineEditor::new();
+        for c in text.chars() {
+            line.update(Key::Char(c));
+        }
+        line
+    }
+
+    fn press(line: &mut LineEditor, keys: &[Key]) {
+        for key in keys {
+            line.update(*key);
+        }
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut line = typed("user:root web");
+        press(&mut line, &[Key::Ctrl('a'), Key::Alt('f'), Key::Char('s')]);
+        assert_eq!("users:root web", line.as_str());
+        press(&mut line, &[Key::Ctrl('k')]);
+        assert_eq!("users", line.as_str());
+        press(
+            &mut line,
+            &[Key::Left, Key::Left, Key::Delete, Key::Backspace],
+        );
+        assert_eq!("uss", line.as_str());
+        press(&mut line, &[Key::End, Key::Ctrl('u')]);
+        assert!(line.is_empty());
+        assert!(!line.update(Key::Char('\n')));
+        assert!(!line.update(Key::Up));
+    }
+
+    #[test]
+    fn test_words() {
+        let mut line = typed("host:10.0.  web-1");
+        press(&mut line, &[Key::Ctrl('w')]);
+        assert_eq!("host:10.0.  ", line.as_str());
+        press(&mut line, &[Key::Ctrl('w')]);
+        assert_eq!("", line.as_str());
+
+        let mut line = typed("web-1 db");
+        press(&mut line, &[Key::Alt('b'), Key::Alt('b')]);
+        assert_eq!(4, line.cursor());
+        press(&mut line, &[Key::Alt('b'), Key::Alt('d')]);
+        assert_eq!("-1 db", line.as_str());
+        press(&mut line, &[Key::End, Key::Alt('\x7f')]);
+        assert_eq!("-1 ", line.as_str());
+    }
+
+    #[test]
+    fn test_unicode() {
+        // "é" as e + combining accent is one grapheme, two chars
+        let mut line = typed("cafe\u{301}名");
+        assert_eq!(6, line.cursor_column());
+        press(&mut line, &[Key::Backspace]);
+        assert_eq!("cafe\u{301}", line.as_str());
+        press(&mut line, &[Key::Backspace]);
+        assert_eq!("caf", line.as_str());
+
+        let mut line = typed("ü👍🏽x");
+        press(&mut line, &[Key::Left, Key::Left, Key::Delete]);
+        assert_eq!("üx", line.as_str());
+        assert_eq!(1, line.cursor_column());
+        press(&mut line, &[Key::Right, Key::Char('!')]);
+        assert_eq!("üx!", line.as_str());
+    }
+}
@@ -0,0 +1,121 @@
+//! Hosts pinned to the top of the list. They're kept one alias to a
+//! line in `~/.local/share/shy/favorites`, or marked in the ssh config
+//! itself with a `#shy: favorite` comment right above the `Host` line.
+
+use {
+    crate::{
+        files::{data_dir, write_atomic},
+        ssh_config::Host,
+    },
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// The pinned hosts, and where to save them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Favorites {
+    path: PathBuf,
+    aliases: Vec<String>,
+}
+
+impl Favorites {
+    /// Load the favorites at `path`. It's fine for it not to exist.
+    pub fn load(path: &Path) -> io::Result<Favorites> {
+        let aliases = match fs::read_to_string(path) {
+            Ok(text) => text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        Ok(Favorites {
+            path: path.to_path_buf(),
+            aliases,
+        })
+    }
+
+    /// Is `host` pinned, here or in its comments?
+    pub fn contains(&self, host: &Host) -> bool {
+        self.aliases.contains(&host.name) || is_annotated(host)
+    }
+
+    /// Pin `alias`, or unpin it if it's pinned, and save. Returns
+    /// whether it's pinned now.
+    pub fn toggle(&mut self, alias: &str) -> io::Result<bool> {
+        let pinned = match self.aliases.iter().position(|a| a == alias) {
+            Some(i) => {
+                self.aliases.remove(i);
+                false
+            }
+            None => {
+                self.aliases.push(alias.to_string());
+                true
+            }
+        };
+        self.save()?;
+        Ok(pinned)
+    }
+
+    /// Write the file, all at once.
+    fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self.aliases.iter().map(|a| format!("{}\n", a)).collect();
+        write_atomic(&self.path, text.as_bytes())
+    }
+}
+
+/// Where the favorites live, in the `data_dir`.
+pub fn favorites_path() -> PathBuf {
+    data_dir().join("favorites")
+}
+
+/// Does `host` have a `#shy: favorite` comment? `#shy: pin` works too.
+pub fn is_annotated(host: &Host) -> bool {
+    host.comments.iter().any(|comment| {
+        comment.strip_prefix("shy:").is_some_and(|rest| {
+            rest.split_whitespace()
+                .any(|w| w == "favorite" || w == "pin")
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::files::ScratchDir};
+
+    #[test]
+    fn test_toggle() {
+        let dir = ScratchDir::new("favorites");
+        let path = dir.join("shy").join("favorites");
+        let mut favorites = Favorites::load(&path).unwrap();
+        let web = Host::new("web");
+        assert!(!favorites.contains(&web));
+
+        assert!(favorites.toggle("web").unwrap());
+        assert!(favorites.toggle("db").unwrap());
+        assert!(Favorites::load(&path).unwrap().contains(&web));
+        assert!(!favorites.toggle("web").unwrap());
+
+        let favorites = Favorites::load(&path).unwrap();
+        assert!(!favorites.contains(&web));
+        assert!(favorites.contains(&Host::new("db")));
+    }
+
+    #[test]
+    fn test_annotated() {
+        let mut host = Host::new("web");
+        host.comments = vec!["the web box".into(), "shy: favorite".into()];
+        assert!(is_annotated(&host));
+        host.comments = vec!["shy:pin".into()];
+        assert!(is_annotated(&host));
+        host.comments = vec!["shy is my favorite".into()];
+        assert!(!is_annotated(&host));
+    }
+}
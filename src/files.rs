@@ -0,0 +1,110 @@
+//! Where shy keeps its own files, and writing files without leaving
+//! half of one behind.
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+/// Where shy keeps what it remembers: `$XDG_DATA_HOME/shy`, or
+/// `~/.local/share/shy`.
+pub fn data_dir() -> PathBuf {
+    let var = |name| env::var_os(name).filter(|v| !v.is_empty());
+    let dir = var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_default();
+    dir.join("shy")
+}
+
+/// Write `contents` over `path` through a temp file that's renamed into
+/// place, so a crash can't leave a half written file behind. A new file
+/// is only readable by you; one that exists keeps its permissions.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let exists = path.exists();
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".shy-tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| match fs::metadata(path) {
+            Ok(meta) if exists => fs::set_permissions(&tmp, meta.permissions()),
+            _ => Ok(()),
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// A directory of a test's own to write in, removed when it's dropped.
+#[cfg(test)]
+pub(crate) struct ScratchDir(PathBuf);
+
+#[cfg(test)]
+impl ScratchDir {
+    /// A new empty directory. `name` is just to tell them apart; tests
+    /// running at the same time each get their own.
+    pub fn new(name: &str) -> ScratchDir {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = env::temp_dir().join(format!(
+            "shy-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        ScratchDir(dir)
+    }
+
+    /// The path to `name` in the directory.
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+#[cfg(test)]
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::os::unix::fs::PermissionsExt};
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = ScratchDir::new("files");
+        let path = dir.join("file");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        write_atomic(&path, b"one\n").unwrap();
+        assert_eq!("one\n", fs::read_to_string(&path).unwrap());
+        assert_eq!(0o600, mode(&path));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_atomic(&path, b"two\n").unwrap();
+        assert_eq!("two\n", fs::read_to_string(&path).unwrap());
+        assert_eq!(0o644, mode(&path));
+        assert_eq!(1, fs::read_dir(&dir.0).unwrap().count());
+    }
+}
@@ -0,0 +1,423 @@
+//! The form for adding, editing and cloning a Host stanza.
+
+use {
+    crate::ssh_config::{
+        document::{quote, Document},
+        expand_tilde, is_alias, SshConfig,
+    },
+    std::io,
+    termion::event::Key,
+};
+
+/// The directives the form edits, after the alias.
+pub const FIELDS: &[&str] = &["HostName", "User", "Port", "IdentityFile", "ProxyJump"];
+
+/// What the form is for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormKind {
+    Add,
+    Edit(String),
+    Clone(String),
+}
+
+/// What to do after a key press.
+#[derive(Debug, PartialEq)]
+pub enum FormAction {
+    Continue,
+    Save,
+    Cancel,
+}
+
+/// An in-progress form.
+#[derive(Debug, Clone)]
+pub struct Form {
+    pub kind: FormKind,
+    /// The alias, then one value per entry in `FIELDS`.
+    pub values: Vec<String>,
+    /// What the values started as, so we only write what changed.
+    original: Vec<String>,
+    /// Other aliases on the stanza being edited, which its fields
+    /// change too.
+    pub shared: Vec<String>,
+    /// Which value has the cursor.
+    pub focus: usize,
+    /// Why the last save didn't work.
+    pub error: Option<String>,
+}
+
+impl Form {
+    /// Start a form. Edit and Clone start with the host's own stanza
+    /// filled in, as it's written in its file: not what other stanzas
+    /// for it add, which saving would copy into this one.
+    pub fn new(kind: FormKind, config: &SshConfig) -> Form {
+        let mut values = vec![String::new(); FIELDS.len() + 1];
+        let mut error = None;
+        let source = match &kind {
+            FormKind::Add => None,
+            FormKind::Edit(alias) | FormKind::Clone(alias) => config.hosts.get(alias),
+        };
+        if let Some(host) = source {
+            values[0] = match kind {
+                FormKind::Clone(_) => format!("{}-copy", host.name),
+                _ => host.name.clone(),
+            };
+            match Document::load(&host.file).and_then(|doc| doc.directives(&host.name)) {
+                Ok(directives) => {
+                    for (i, key) in FIELDS.iter().enumerate() {
+                        if let Some((_, value)) =
+                            directives.iter().find(|(k, _)| k.eq_ignore_ascii_case(key))
+                        {
+                            values[i + 1] = value.clone();
+                        }
+                    }
+                }
+                Err(err) => error = Some(err.to_string()),
+            }
+        }
+
+        let shared = match (&kind, source) {
+            (FormKind::Edit(alias), Some(host)) => host
+                .patterns
+                .iter()
+                .filter(|p| *p != alias)
+                .cloned()
+                .collect(),
+            _ => vec![],
+        };
+
+        Form {
+            original: match kind {
+                FormKind::Edit(_) => values.clone(),
+                _ => vec![String::new(); values.len()],
+            },
+            shared,
+            kind,
+            values,
+            focus: 0,
+            error,
+        }
+    }
+
+    /// Field labels, alias first.
+    pub fn labels() -> impl Iterator<Item = &'static str> {
+        Some("Host").into_iter().chain(FIELDS.iter().cloned())
+    }
+
+    /// A title for the top of the screen.
+    pub fn title(&self) -> String {
+        match &self.kind {
+            FormKind::Add => "New host".into(),
+            FormKind::Edit(alias) if !self.shared.is_empty() => format!(
+                "Edit {} (its settings are shared with {})",
+                alias,
+                self.shared.join(" ")
+            ),
+            FormKind::Edit(alias) => format!("Edit {}", alias),
+            FormKind::Clone(alias) => format!("Clone {}", alias),
+        }
+    }
+
+    /// Handle a key press.
+    pub fn update(&mut self, key: Key) -> FormAction {
+        match key {
+            Key::Esc | Key::Ctrl('c') => return FormAction::Cancel,
+            Key::Char('\n') => return FormAction::Save,
+            Key::Char('\t') | Key::Down | Key::Ctrl('n') => {
+                self.focus = (self.focus + 1) % self.values.len();
+            }
+            Key::BackTab | Key::Up | Key::Ctrl('p') => {
+                self.focus = (self.focus + self.values.len() - 1) % self.values.len();
+            }
+            Key::Backspace => {
+                self.values[self.focus].pop();
+            }
+            Key::Char(c) => self.values[self.focus].push(c),
+            _ => {}
+        }
+        FormAction::Continue
+    }
+
+    /// Check the values make sense before we write them anywhere.
+    fn validate(&self, config: &SshConfig) -> Result<(), String> {
+        let alias = self.values[0].trim();
+        if alias.is_empty() {
+            return Err("Host can't be empty".into());
+        }
+        let renamed = match &self.kind {
+            FormKind::Edit(old) => old != alias,
+            _ => true,
+        };
+        // `Host "my box"` can't be typed in, but can be kept
+        if !is_alias(alias) || (renamed && alias.contains(char::is_whitespace)) {
+            return Err(format!("{} is a pattern, not a host", alias));
+        }
+        if renamed && config.hosts.contains_key(alias) {
+            return Err(format!("Host {} already exists", alias));
+        }
+
+        let port = self.values[1 + FIELDS.iter().position(|f| *f == "Port").unwrap_or(0)].trim();
+        if !port.is_empty() && port.parse::<u16>().map_or(true, |p| p == 0) {
+            return Err(format!("{} isn't a port", port));
+        }
+        Ok(())
+    }
+
+    /// Write the form to the config file the host lives in, or
+    /// `config_path` for new hosts. Returns the alias to select.
+    pub fn save(&mut self, config_path: &str, config: &SshConfig) -> io::Result<String> {
+        if let Err(err) = self.validate(config) {
+            self.error = Some(err.clone());
+            return Err(io::Error::other(err));
+        }
+
+        let result = self.write(config_path, config);
+        if let Err(err) = &result {
+            self.error = Some(err.to_string());
+        }
+        result
+    }
+
+    fn write(&self, config_path: &str, config: &SshConfig) -> io::Result<String> {
+        let alias = self.values[0].trim().to_string();
+        let file = match &self.kind {
+            FormKind::Add => expand_tilde(config_path),
+            FormKind::Edit(source) | FormKind::Clone(source) => config
+                .hosts
+                .get(source)
+                .map(|h| h.file.clone())
+                .unwrap_or_default(),
+        };
+        let mut doc = Document::load(&file)?;
+
+        match &self.kind {
+            FormKind::Add => doc.add_host(&alias, &self.options(vec![]))?,
+            FormKind::Clone(source) => {
+                let options = self.options(doc.directives(source)?);
+                doc.add_host(&alias, &options)?;
+            }
+            FormKind::Edit(source) => {
+                for (i, key) in FIELDS.iter().enumerate() {
+                    let value = self.values[i + 1].trim();
+                    if value == self.original[i + 1].trim() {
+                        continue;
+                    }
+                    if value.is_empty() {
+                        doc.remove(source, key)?;
+                    } else {
+                        doc.set(source, key, &quote(value))?;
+                    }
+                }
+                if *source != alias {
+                    doc.rename_host(source, &alias)?;
+                }
+            }
+        }
+
+        doc.save(&file)?;
+        Ok(alias)
+    }
+
+    /// `existing` directives with the form's fields swapped in: changed
+    /// ones where they were, new ones at the end, and blank ones gone.
+    /// Only the first of each field is the form's; later ones, like a
+    /// second IdentityFile, are kept as they are.
+    fn options(&self, existing: Vec<(String, String)>) -> Vec<(String, String)> {
+        let mut options = vec![];
+        let mut done = vec![false; FIELDS.len()];
+
+        for (key, value) in existing {
+            match FIELDS.iter().position(|f| f.eq_ignore_ascii_case(&key)) {
+                Some(i) if done[i] => options.push((key, value)),
+                Some(i) => {
+                    done[i] = true;
+                    let value = self.values[i + 1].trim();
+                    if !value.is_empty() {
+                        options.push((key, quote(value)));
+                    }
+                }
+                None => options.push((key, value)),
+            }
+        }
+
+        for (i, key) in FIELDS.iter().enumerate() {
+            let value = self.values[i + 1].trim();
+            if !done[i] && !value.is_empty() {
+                options.push((key.to_string(), quote(value)));
+            }
+        }
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{files::ScratchDir, ssh_config::load_ssh_config},
+        std::fs,
+    };
+
+    /// Copy tests/test_config somewhere we can scribble on it, until
+    /// the ScratchDir goes.
+    fn scratch_config(name: &str) -> (ScratchDir, String) {
+        scratch_text(name, &fs::read_to_string("./tests/test_config").unwrap())
+    }
+
+    /// A scratch config holding just `text`.
+    fn scratch_text(name: &str, text: &str) -> (ScratchDir, String) {
+        let dir = ScratchDir::new(name);
+        let path = dir.join("config");
+        fs::write(&path, text).unwrap();
+        (dir, path.to_string_lossy().to_string())
+    }
+
+    const KEYS: &str = "Host \"my box\" other\n    User me\n    IdentityFile ~/.ssh/one\n    IdentityFile ~/.ssh/two\n";
+
+    fn type_str(form: &mut Form, text: &str) {
+        for c in text.chars() {
+            form.update(Key::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_add() {
+        let (_dir, path) = scratch_config("form-add");
+        let config = load_ssh_config(&path).unwrap();
+        let mut form = Form::new(FormKind::Add, &config);
+        type_str(&mut form, "newbox");
+        form.update(Key::Char('\t'));
+        type_str(&mut form, "10.0.0.5");
+        form.update(Key::Down);
+        form.update(Key::Down);
+        type_str(&mut form, "22x");
+        assert!(form.save(&path, &config).is_err());
+        assert_eq!(Some("22x isn't a port".into()), form.error);
+
+        form.update(Key::Backspace);
+        assert_eq!("newbox", form.save(&path, &config).unwrap());
+
+        let config = load_ssh_config(&path).unwrap();
+        let host = config.resolve("newbox");
+        assert_eq!("10.0.0.5", host.hostname());
+        assert_eq!(Some("22"), host.port());
+        assert_eq!(Some("nixcraft"), host.user());
+    }
+
+    #[test]
+    fn test_edit() {
+        let (_dir, path) = scratch_config("form-edit");
+        let config = load_ssh_config(&path).unwrap();
+        let mut form = Form::new(FormKind::Edit("nas01".into()), &config);
+        assert_eq!("nas01", form.values[0]);
+        assert_eq!("192.168.1.100", form.values[1]);
+        assert_eq!("root", form.values[2]);
+
+        type_str(&mut form, "-old");
+        form.update(Key::Down);
+        form.update(Key::Down);
+        form.values[2].clear();
+        form.save(&path, &config).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("Host nas01-old\n     HostName 192.168.1.100\n     IdentityFile"));
+        let config = load_ssh_config(&path).unwrap();
+        assert!(!config.hosts.contains_key("nas01"));
+        assert_eq!(None, config.hosts["nas01-old"].user());
+
+        let mut form = Form::new(FormKind::Edit("docker1".into()), &config);
+        form.values[0] = "docker2".into();
+        assert!(form.save(&path, &config).is_err());
+    }
+
+    #[test]
+    fn test_clone() {
+        let (_dir, path) = scratch_config("form-clone");
+        let config = load_ssh_config(&path).unwrap();
+        let mut form = Form::new(FormKind::Clone("nixcraft".into()), &config);
+        assert_eq!("nixcraft-copy", form.values[0]);
+        form.values[1] = "server2.cyberciti.biz".into();
+        form.save(&path, &config).unwrap();
+
+        let config = load_ssh_config(&path).unwrap();
+        let copy = &config.hosts["nixcraft-copy"];
+        assert_eq!("server2.cyberciti.biz", copy.hostname());
+        assert_eq!(Some("4242"), copy.port());
+        assert_eq!(
+            vec!["hostname", "user", "port", "identityfile"],
+            copy.options.keys().collect::<Vec<_>>()
+        );
+        assert_eq!("server1.cyberciti.biz", config.hosts["nixcraft"].hostname());
+    }
+
+    #[test]
+    fn test_edit_identity_files() {
+        let (_dir, path) = scratch_text("form-edit-keys", KEYS);
+        let config = load_ssh_config(&path).unwrap();
+        let mut form = Form::new(FormKind::Edit("my box".into()), &config);
+        assert_eq!(
+            "Edit my box (its settings are shared with other)",
+            form.title()
+        );
+        assert_eq!("~/.ssh/one", form.values[4]);
+
+        // unchanged, the alias can keep its space
+        form.values[4] = "~/.ssh/uno".into();
+        form.save(&path, &config).unwrap();
+        assert_eq!(
+            "Host \"my box\" other\n    User me\n    IdentityFile ~/.ssh/uno\n    IdentityFile ~/.ssh/two\n",
+            fs::read_to_string(&path).unwrap()
+        );
+
+        let config = load_ssh_config(&path).unwrap();
+        let mut form = Form::new(FormKind::Edit("other".into()), &config);
+        form.values[4].clear();
+        form.save(&path, &config).unwrap();
+        assert_eq!(
+            "Host \"my box\" other\n    User me\n    IdentityFile ~/.ssh/two\n",
+            fs::read_to_string(&path).unwrap()
+        );
+
+        let mut form = Form::new(FormKind::Edit("other".into()), &config);
+        form.values[0] = "other box".into();
+        assert!(form.save(&path, &config).is_err());
+    }
+
+    #[test]
+    fn test_edit_split_stanzas() {
+        let text = "Host box\n    HostName one\n\nHost box\n    User extra\n";
+        let (_dir, path) = scratch_text("form-edit-split", text);
+        let config = load_ssh_config(&path).unwrap();
+        assert_eq!(Some("extra"), config.hosts["box"].user());
+
+        // the second stanza's User isn't this one's to show or save
+        let mut form = Form::new(FormKind::Edit("box".into()), &config);
+        assert_eq!("one", form.values[1]);
+        assert_eq!("", form.values[2]);
+        form.values[2] = "me".into();
+        form.save(&path, &config).unwrap();
+        assert_eq!(
+            "Host box\n    HostName one\n    User me\n\nHost box\n    User extra\n",
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_clone_identity_files() {
+        let (_dir, path) = scratch_text("form-clone-keys", KEYS);
+        let config = load_ssh_config(&path).unwrap();
+        let mut form = Form::new(FormKind::Clone("other".into()), &config);
+        form.values[2] = "you".into();
+        form.save(&path, &config).unwrap();
+
+        let config = load_ssh_config(&path).unwrap();
+        let copy = &config.hosts["other-copy"];
+        assert_eq!(Some("you"), copy.user());
+        assert_eq!(
+            vec!["~/.ssh/one", "~/.ssh/two"],
+            copy.options["identityfile"]
+                .iter()
+                .map(|v| v.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Every host shy has connected to, and when. It's kept one launch to
+//! a line, as a Unix timestamp and an alias separated by a tab, in
+//! `~/.local/share/shy/history`.
+
+use {
+    crate::files::data_dir,
+    std::{
+        collections::HashMap,
+        fs::{self, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+/// How much a visit counts for, by how long ago it was.
+const WEIGHTS: &[(u64, i64)] = &[(DAY, 100), (7 * DAY, 70), (30 * DAY, 50), (90 * DAY, 30)];
+
+/// What a visit counts for once it's older than all of `WEIGHTS`.
+const OLD_WEIGHT: i64 = 10;
+
+/// One launch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visit {
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub alias: String,
+}
+
+/// Past launches, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    pub visits: Vec<Visit>,
+}
+
+impl History {
+    /// Parse a history file. Lines that don't make sense are skipped,
+    /// since losing one visit isn't worth refusing to start over.
+    pub fn parse(text: &str) -> History {
+        let visits = text
+            .lines()
+            .filter_map(|line| {
+                let (time, alias) = line.split_once('\t')?;
+                Some(Visit {
+                    time: time.parse().ok()?,
+                    alias: alias.to_string(),
+                })
+            })
+            .filter(|visit| !visit.alias.is_empty())
+            .collect();
+        History { visits }
+    }
+
+    /// Frecency scores by alias: each visit adds points, more for
+    /// recent ones, so hosts used often and lately come out on top.
+    pub fn scores(&self, now: u64) -> HashMap<&str, i64> {
+        let mut scores = HashMap::new();
+        for visit in &self.visits {
+            let age = now.saturating_sub(visit.time);
+            let weight = WEIGHTS
+                .iter()
+                .find(|(max, _)| age < *max)
+                .map_or(OLD_WEIGHT, |(_, weight)| *weight);
+            *scores.entry(visit.alias.as_ref()).or_insert(0) += weight;
+        }
+        scores
+    }
+}
+
+/// Points added to a host's search score for its frecency: enough to
+/// settle close matches, not enough to beat a much better one.
+pub fn boost(score: i64) -> i64 {
+    (score.max(0) as f64).sqrt() as i64
+}
+
+/// Where the history lives, in the `data_dir`.
+pub fn history_path() -> PathBuf {
+    data_dir().join("history")
+}
+
+/// Load the history at `path`. It's fine for it not to exist yet.
+pub fn load_history(path: &Path) -> io::Result<History> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(History::parse(&text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(History::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Add a launch of `alias` at `time` to the history at `path`.
+pub fn record(path: &Path, alias: &str, time: u64) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}\t{}", time, alias)
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// A timestamp as `YYYY-MM-DD HH:MM` in UTC.
+pub fn format_time(time: u64) -> String {
+    // days to a civil date, from Howard Hinnant's `civil_from_days`
+    let days = (time / DAY) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let secs = time % DAY;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / HOUR,
+        secs % HOUR / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::files::ScratchDir};
+
+    #[test]
+    fn test_scores() {
+        let now = 1_000 * DAY;
+        let history = History::parse(&format!(
+            "{}\tweb\n{}\tweb\n{}\tdb\nnonsense\n{}\t\n{}\told\n",
+            now - HOUR,
+            now - 2 * DAY,
+            now - 10,
+            now,
+            now - 365 * DAY,
+        ));
+        assert_eq!(4, history.visits.len());
+
+        let scores = history.scores(now);
+        assert_eq!(Some(&170), scores.get("web"));
+        assert_eq!(Some(&100), scores.get("db"));
+        assert_eq!(Some(&10), scores.get("old"));
+        assert_eq!(10, boost(100));
+        assert_eq!(0, boost(-5));
+    }
+
+    #[test]
+    fn test_record() {
+        let dir = ScratchDir::new("history");
+        let path = dir.join("shy").join("history");
+        assert_eq!(History::default(), load_history(&path).unwrap());
+
+        record(&path, "web", 10).unwrap();
+        record(&path, "db", 20).unwrap();
+        let history = load_history(&path).unwrap();
+        assert_eq!(
+            vec![(10, "web"), (20, "db")],
+            history
+                .visits
+                .iter()
+                .map(|v| (v.time, v.alias.as_ref()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!("1970-01-01 00:00", format_time(0));
+        assert_eq!("2000-02-29 13:05", format_time(951_829_500));
+        assert_eq!("2026-10-17 23:59", format_time(1_792_281_540));
+    }
+}
@@ -0,0 +1,423 @@
+//! Turns key presses into actions. Each mode has its own bindings,
+//! which can be sequences like `g g`, and the config file can change
+//! any of them.
+
+use {
+    crate::editor::{Edit, DEFAULT_KEYS as EDIT_KEYS, EDITS},
+    indexmap::IndexMap,
+    std::fmt,
+    termion::event::Key,
+};
+
+/// Something a key can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Refresh,
+    /// Enter search mode.
+    Search,
+    /// Clear the search input, or leave search mode if it's empty.
+    Cancel,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    First,
+    Last,
+    /// The hosts at the top, middle and bottom of the screen.
+    Top,
+    Middle,
+    Bottom,
+    Connect,
+    ToggleDetail,
+    AddHost,
+    EditHost,
+    CloneHost,
+    DeleteHost,
+    /// Pin the selected host to the top of the list, or unpin it.
+    ToggleFavorite,
+    /// Pick something else to do with the selected host, like sftp.
+    ActionMenu,
+    /// Change the search input.
+    Edit(Edit),
+}
+
+/// Every action but the edits, by the name used for it in the config
+/// file.
+const ACTIONS: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("refresh", Action::Refresh),
+    ("search", Action::Search),
+    ("cancel", Action::Cancel),
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("page-up", Action::PageUp),
+    ("page-down", Action::PageDown),
+    ("half-page-up", Action::HalfPageUp),
+    ("half-page-down", Action::HalfPageDown),
+    ("first", Action::First),
+    ("last", Action::Last),
+    ("top", Action::Top),
+    ("middle", Action::Middle),
+    ("bottom", Action::Bottom),
+    ("connect", Action::Connect),
+    ("toggle-detail", Action::ToggleDetail),
+    ("add-host", Action::AddHost),
+    ("edit-host", Action::EditHost),
+    ("clone-host", Action::CloneHost),
+    ("delete-host", Action::DeleteHost),
+    ("toggle-favorite", Action::ToggleFavorite),
+    ("action-menu", Action::ActionMenu),
+];
+
+impl Action {
+    /// Look an action up by its config file name.
+    pub fn from_name(name: &str) -> Option<Action> {
+        let name = name.to_lowercase();
+        ACTIONS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, a)| *a)
+            .or_else(|| {
+                EDITS
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, e)| Action::Edit(*e))
+            })
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Action::Edit(edit) => EDITS.iter().find(|(_, e)| e == edit).map(|(n, _)| *n),
+            _ => ACTIONS.iter().find(|(_, a)| a == self).map(|(n, _)| *n),
+        };
+        write!(f, "{}", name.unwrap_or("?"))
+    }
+}
+
+/// Which set of bindings to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyMode {
+    Nav,
+    Search,
+}
+
+impl KeyMode {
+    /// The name of the mode's table in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyMode::Nav => "nav",
+            KeyMode::Search => "search",
+        }
+    }
+}
+
+/// What the keys pressed so far add up to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Found(Action),
+    /// The start of a longer binding; wait for the next key.
+    Prefix,
+    Unbound,
+}
+
+/// A key sequence and what it does.
+pub type Bindings = IndexMap<Vec<Key>, Action>;
+
+/// Key bindings for every mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    nav: Bindings,
+    search: Bindings,
+}
+
+impl Default for Keymap {
+    /// shy's own bindings.
+    fn default() -> Keymap {
+        use Action::*;
+        let nav = [
+            ("q", Quit),
+            ("esc", Quit),
+            ("ctrl-c", Quit),
+            ("r", Refresh),
+            ("f5", Refresh),
+            ("i", Search),
+            ("s", Search),
+            ("/", Search),
+            ("f", Search),
+            ("k", Up),
+            ("up", Up),
+            ("ctrl-p", Up),
+            ("j", Down),
+            ("down", Down),
+            ("ctrl-n", Down),
+            ("g g", First),
+            ("G", Last),
+            ("ctrl-u", HalfPageUp),
+            ("ctrl-d", HalfPageDown),
+            ("ctrl-b", PageUp),
+            ("pageup", PageUp),
+            ("-", PageUp),
+            ("ctrl-f", PageDown),
+            ("pagedown", PageDown),
+            ("space", PageDown),
+            ("H", Top),
+            ("M", Middle),
+            ("L", Bottom),
+            ("enter", Connect),
+            ("tab", ToggleDetail),
+            ("a", AddHost),
+            ("e", EditHost),
+            ("c", CloneHost),
+            ("d", DeleteHost),
+            ("p", ToggleFavorite),
+            ("o", ActionMenu),
+        ];
+        let search = [
+            ("esc", Cancel),
+            ("ctrl-c", Cancel),
+            ("up", Up),
+            ("ctrl-p", Up),
+            ("down", Down),
+            ("ctrl-n", Down),
+            ("pageup", PageUp),
+            ("pagedown", PageDown),
+            ("enter", Connect),
+            ("ctrl-o", ActionMenu),
+            ("tab", ToggleDetail),
+        ];
+
+        let parse = |keys: &str| parse_keys(keys).expect("bad default key");
+        Keymap {
+            nav: nav.iter().map(|(k, a)| (parse(k), *a)).collect(),
+            search: search
+                .iter()
+                .map(|(k, a)| (parse(k), *a))
+                .chain(EDIT_KEYS.iter().map(|(k, e)| (vec![*k], Action::Edit(*e))))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The bindings for `mode`.
+    pub fn bindings(&self, mode: KeyMode) -> &Bindings {
+        match mode {
+            KeyMode::Nav => &self.nav,
+            KeyMode::Search => &self.search,
+        }
+    }
+
+    /// Bind `keys`, like `ctrl-j` or `g g`, to the action named
+    /// `action`. `none` unbinds them. Bindings the new one would hide,
+    /// or be hidden by, are dropped: binding `g` removes `g g`.
+    pub fn bind(&mut self, mode: KeyMode, keys: &str, action: &str) -> Result<(), String> {
+        let keys = parse_keys(keys)?;
+        let action = match action.to_lowercase().as_ref() {
+            "none" => None,
+            _ => Some(Action::from_name(action).ok_or(format!("unknown action: {}", action))?),
+        };
+
+        let bindings = match mode {
+            KeyMode::Nav => &mut self.nav,
+            KeyMode::Search => &mut self.search,
+        };
+        bindings.retain(|k, _| !k.starts_with(&keys) && !keys.starts_with(k));
+        if let Some(action) = action {
+            bindings.insert(keys, action);
+        }
+        Ok(())
+    }
+
+    /// What the keys pressed so far in `mode` do.
+    pub fn lookup(&self, mode: KeyMode, keys: &[Key]) -> Lookup {
+        let bindings = self.bindings(mode);
+        if let Some(action) = bindings.get(keys) {
+            Lookup::Found(*action)
+        } else if bindings.keys().any(|k| k.starts_with(keys)) {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+}
+
+/// Parse a space separated key sequence, like `g g` or `ctrl-x k`.
+pub fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    let keys = text
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key".into());
+    }
+    Ok(keys)
+}
+
+/// Parse one key: a character like `j` or `G`, a name like `enter` or
+/// `pageup`, or `ctrl-` or `alt-` and a character.
+pub fn parse_key(text: &str) -> Result<Key, String> {
+    let mut chars = text.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Char(c));
+    }
+
+    let lower = text.to_lowercase();
+    let key = match lower.as_ref() {
+        "space" => Key::Char(' '),
+        "enter" | "return" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "esc" | "escape" => Key::Esc,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "insert" => Key::Insert,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backtab" | "shift-tab" => Key::BackTab,
+        "alt-backspace" => Key::Alt('\x7f'),
+        _ => {
+            if let Some(rest) = lower.strip_prefix("ctrl-") {
+                Key::Ctrl(single(rest).ok_or(format!("unknown key: {}", text))?)
+            } else if let Some(rest) = text.get(4..).filter(|_| lower.starts_with("alt-")) {
+                Key::Alt(single(rest).ok_or(format!("unknown key: {}", text))?)
+            } else if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Key::F(n)
+            } else {
+                return Err(format!("unknown key: {}", text));
+            }
+        }
+    };
+    Ok(key)
+}
+
+/// The only char in `text`.
+fn single(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// How `parse_key` would spell a key.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "space".into(),
+        Key::Char('\n') => "enter".into(),
+        Key::Char('\t') => "tab".into(),
+        Key::Char(c) => c.to_string(),
+        Key::Alt('\x7f') => "alt-backspace".into(),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::F(n) => format!("f{}", n),
+        Key::Esc => "esc".into(),
+        Key::Backspace => "backspace".into(),
+        Key::Delete => "delete".into(),
+        Key::Insert => "insert".into(),
+        Key::Up => "up".into(),
+        Key::Down => "down".into(),
+        Key::Left => "left".into(),
+        Key::Right => "right".into(),
+        Key::Home => "home".into(),
+        Key::End => "end".into(),
+        Key::PageUp => "pageup".into(),
+        Key::PageDown => "pagedown".into(),
+        Key::BackTab => "backtab".into(),
+        _ => "?".into(),
+    }
+}
+
+/// How `parse_keys` would spell a key sequence.
+pub fn keys_name(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|k| key_name(*k))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(Ok(Key::Char('G')), parse_key("G"));
+        assert_eq!(Ok(Key::Ctrl('d')), parse_key("Ctrl-D"));
+        assert_eq!(Ok(Key::Alt('B')), parse_key("alt-B"));
+        assert_eq!(Ok(Key::F(5)), parse_key("F5"));
+        assert_eq!(Ok(Key::Char(' ')), parse_key("space"));
+        assert!(parse_key("ctrl-").is_err());
+        assert!(parse_key("gg").is_err());
+        assert_eq!(Ok(vec![Key::Char('g'), Key::Char('g')]), parse_keys("g  g"));
+        assert!(parse_keys(" ").is_err());
+
+        for name in &[
+            "alt-backspace",
+            "enter",
+            "ctrl-w",
+            "pagedown",
+            "é",
+            "backtab",
+        ] {
+            assert_eq!(*name, key_name(parse_key(name).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let keymap = Keymap::default();
+        let g = Key::Char('g');
+        assert_eq!(Lookup::Prefix, keymap.lookup(KeyMode::Nav, &[g]));
+        assert_eq!(
+            Lookup::Found(Action::First),
+            keymap.lookup(KeyMode::Nav, &[g, g])
+        );
+        assert_eq!(Lookup::Unbound, keymap.lookup(KeyMode::Search, &[g]));
+        assert_eq!(
+            Lookup::Found(Action::Edit(Edit::KillLine)),
+            keymap.lookup(KeyMode::Search, &[Key::Ctrl('k')])
+        );
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut keymap = Keymap::default();
+        keymap.bind(KeyMode::Search, "ctrl-j", "down").unwrap();
+        keymap.bind(KeyMode::Search, "j k", "cancel").unwrap();
+        keymap.bind(KeyMode::Nav, "g", "last").unwrap();
+        keymap.bind(KeyMode::Nav, "q", "none").unwrap();
+
+        let lookup = |mode, keys: &str| keymap.lookup(mode, &parse_keys(keys).unwrap());
+        assert_eq!(
+            Lookup::Found(Action::Down),
+            lookup(KeyMode::Search, "ctrl-j")
+        );
+        assert_eq!(Lookup::Prefix, lookup(KeyMode::Search, "j"));
+        assert_eq!(
+            Lookup::Found(Action::Cancel),
+            lookup(KeyMode::Search, "j k")
+        );
+        assert_eq!(Lookup::Found(Action::Last), lookup(KeyMode::Nav, "g"));
+        assert_eq!(Lookup::Unbound, lookup(KeyMode::Nav, "q"));
+        // other modes aren't touched
+        assert_eq!(Lookup::Unbound, lookup(KeyMode::Nav, "ctrl-j"));
+
+        assert!(keymap.bind(KeyMode::Nav, "x", "explode").is_err());
+        assert!(keymap.bind(KeyMode::Nav, "hyper-x", "quit").is_err());
+        assert_eq!("kill-word", Action::Edit(Edit::KillWord).to_string());
+        assert_eq!(
+            Some(Action::HalfPageDown),
+            Action::from_name("half-page-down")
+        );
+    }
+}
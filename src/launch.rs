@@ -0,0 +1,484 @@
+//! Connecting to a host: the command to run, filled in from a
+//! template like `mosh {user}@{hostname}`, the other actions that can
+//! be run on a host instead, and what to do about TERM.
+
+use {
+    crate::ssh_config::{local_user, Host},
+    indexmap::IndexMap,
+    std::{env, fmt},
+};
+
+/// What the placeholders in a template can be.
+const PLACEHOLDERS: &[&str] = &["alias", "host", "hostname", "user", "port"];
+
+/// TERM values that pretty much every server has terminfo for.
+const COMMON_TERMS: &[&str] = &[
+    "xterm",
+    "xterm-color",
+    "xterm-256color",
+    "screen",
+    "screen-256color",
+    "tmux",
+    "tmux-256color",
+    "linux",
+    "vt100",
+    "vt102",
+    "vt220",
+    "ansi",
+    "dumb",
+];
+
+/// What `Term::Auto` sets TERM to when it doesn't look common.
+const FALLBACK_TERM: &str = "xterm-256color";
+
+/// The action menu's entries, unless the config file changes them.
+const DEFAULT_ACTIONS: &[(&str, &str)] = &[
+    ("sftp", "sftp {alias}"),
+    ("scp", "scp {?Local file} {alias}:{?Remote path=.}"),
+    ("mosh", "mosh {alias}"),
+    ("ssh-copy-id", "ssh-copy-id {alias}"),
+    ("socks", "ssh -N -D {?SOCKS port=1080} {alias}"),
+    (
+        "forward",
+        "ssh -N -L {?Local port}:{?Remote host=localhost}:{?Remote port} {alias}",
+    ),
+    ("run", "ssh -t {alias} {?Command}"),
+];
+
+/// Actions by name, in menu order.
+pub type Actions = IndexMap<String, Launcher>;
+
+/// The actions shy comes with.
+pub fn default_actions() -> Actions {
+    DEFAULT_ACTIONS
+        .iter()
+        .map(|(name, template)| {
+            let launcher = Launcher::parse(template).expect("bad default action");
+            (name.to_string(), launcher)
+        })
+        .collect()
+}
+
+/// What was picked: a host, and an action to run on it instead of
+/// the launcher, with the answers to its questions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Launch {
+    pub alias: String,
+    pub action: Option<String>,
+    pub answers: Vec<String>,
+}
+
+impl Launch {
+    /// Connect to `alias` with the launcher.
+    pub fn connect(alias: &str) -> Launch {
+        Launch {
+            alias: alias.to_string(),
+            action: None,
+            answers: vec![],
+        }
+    }
+}
+
+/// Something to ask before running a command, from a `{?Label}` or
+/// `{?Label=default}` placeholder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Question {
+    pub label: String,
+    pub default: String,
+}
+
+/// A command template. `{alias}` (or `{host}`), `{hostname}`, `{user}`
+/// and `{port}` are filled in from the host, and templates without
+/// any get the alias added to the end. `{?Label}` is filled in with
+/// the answer to a question.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Launcher {
+    template: String,
+    words: Vec<String>,
+}
+
+impl Launcher {
+    /// Split a template into words like a shell would, quotes and
+    /// backslashes included, and check its placeholders.
+    pub fn parse(template: &str) -> Result<Launcher, String> {
+        let mut words = split_words(template)?;
+        if words.is_empty() {
+            return Err("empty command".into());
+        }
+
+        let mut has_placeholder = false;
+        for word in &words {
+            for piece in pieces(word) {
+                match piece {
+                    Piece::Text(_) | Piece::Question(_) => {}
+                    Piece::Name(name) if PLACEHOLDERS.contains(&name) => has_placeholder = true,
+                    Piece::Name(name) => return Err(format!("unknown placeholder: {{{}}}", name)),
+                }
+            }
+        }
+        if !has_placeholder {
+            words.push("{alias}".into());
+        }
+
+        Ok(Launcher {
+            template: template.to_string(),
+            words,
+        })
+    }
+
+    /// The questions to ask before running, in order, each once.
+    pub fn questions(&self) -> Vec<Question> {
+        let mut questions: Vec<Question> = vec![];
+        for word in &self.words {
+            for piece in pieces(word) {
+                if let Piece::Question(question) = piece {
+                    if !questions.iter().any(|q| q.label == question.label) {
+                        questions.push(question);
+                    }
+                }
+            }
+        }
+        questions
+    }
+
+    /// The program and arguments to run on `host`, which should
+    /// already be resolved. `answers` go with `questions()`.
+    pub fn command(&self, host: &Host, answers: &[String]) -> Vec<String> {
+        let questions = self.questions();
+        let value = |name: &str| match name {
+            "alias" | "host" => host.name.clone(),
+            "hostname" => host.hostname().to_string(),
+            "user" => host.user().map_or_else(local_user, String::from),
+            "port" => host.port().unwrap_or("22").to_string(),
+            _ => String::new(),
+        };
+        self.words
+            .iter()
+            .map(|word| {
+                pieces(word)
+                    .into_iter()
+                    .map(|piece| match piece {
+                        Piece::Text(text) => text.to_string(),
+                        Piece::Name(name) => value(name),
+                        Piece::Question(question) => questions
+                            .iter()
+                            .position(|q| q.label == question.label)
+                            .and_then(|i| answers.get(i))
+                            .cloned()
+                            .unwrap_or(question.default),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl Default for Launcher {
+    fn default() -> Launcher {
+        Launcher::parse("ssh {alias}").unwrap()
+    }
+}
+
+impl fmt::Display for Launcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+/// What to do with TERM before connecting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// Leave it alone.
+    Keep,
+    /// Set it to this.
+    Set(String),
+    /// Leave it alone if it's one every server knows, and otherwise
+    /// use xterm-256color. Servers without terminfo for something like
+    /// xterm-kitty make a mess of the screen.
+    Auto,
+}
+
+impl Term {
+    /// `keep`, `auto`, or a TERM value to use.
+    pub fn parse(text: &str) -> Result<Term, String> {
+        match text {
+            "" => Err("term can't be empty".into()),
+            "keep" => Ok(Term::Keep),
+            "auto" => Ok(Term::Auto),
+            term => Ok(Term::Set(term.to_string())),
+        }
+    }
+
+    /// The TERM to connect with, given the current one, or None to
+    /// leave it alone.
+    pub fn choose(&self, current: Option<&str>) -> Option<String> {
+        match self {
+            Term::Keep => None,
+            Term::Set(term) => Some(term.clone()),
+            Term::Auto => match current {
+                Some(term) if !COMMON_TERMS.contains(&term) => Some(FALLBACK_TERM.into()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Change TERM for the programs we run.
+    pub fn apply(&self) {
+        let current = env::var("TERM").ok();
+        if let Some(term) = self.choose(current.as_deref()) {
+            env::set_var("TERM", term);
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Term::Keep => write!(f, "keep"),
+            Term::Auto => write!(f, "auto"),
+            Term::Set(term) => write!(f, "{}", term),
+        }
+    }
+}
+
+/// `words` as a line a shell would split back into them, quoting
+/// the ones that need it.
+pub fn shell_line(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| {
+            let plain = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+            if !word.is_empty() && word.chars().all(plain) {
+                word.clone()
+            } else {
+                format!("'{}'", word.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split `text` on whitespace, except inside quotes and `{?...}`.
+/// Single quotes keep everything as is, and a backslash escapes the
+/// next char anywhere else.
+fn split_words(text: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quote".into()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quote".into()),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            // a question's label can have spaces in it
+            '{' if chars.clone().next() == Some('?') => {
+                let word = word.get_or_insert_with(String::new);
+                word.push(c);
+                for c in chars.by_ref() {
+                    word.push(c);
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Part of a word in a template.
+#[derive(Debug, PartialEq)]
+enum Piece<'a> {
+    Text(&'a str),
+    /// `{name}`
+    Name(&'a str),
+    /// `{?Label=default}`
+    Question(Question),
+}
+
+/// Split `word` into text and placeholders. Braces around anything
+/// but letters, or `?` and a label, are left alone.
+fn pieces(word: &str) -> Vec<Piece<'_>> {
+    let mut pieces = vec![];
+    let mut rest = word;
+    let mut text_start = 0;
+    let mut at = 0;
+    while let Some(start) = rest.find('{') {
+        let inner = &rest[start + 1..];
+        let end = match inner.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let name = &inner[..end];
+        let piece = if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic()) {
+            Some(Piece::Name(name))
+        } else if let Some(question) = name.strip_prefix('?').filter(|q| !q.is_empty()) {
+            let (label, default) = question.split_once('=').unwrap_or((question, ""));
+            Some(Piece::Question(Question {
+                label: label.to_string(),
+                default: default.to_string(),
+            }))
+        } else {
+            None
+        };
+
+        match piece {
+            Some(piece) => {
+                if at + start > text_start {
+                    pieces.push(Piece::Text(&word[text_start..at + start]));
+                }
+                pieces.push(piece);
+                at += start + end + 2;
+                text_start = at;
+                rest = &word[at..];
+            }
+            None => {
+                at += start + 1;
+                rest = &word[at..];
+            }
+        }
+    }
+    if text_start < word.len() {
+        pieces.push(Piece::Text(&word[text_start..]));
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host() -> Host {
+        let mut host = Host::new("web");
+        host.insert("HostName", "10.0.0.5");
+        host.insert("Port", "2222");
+        host.insert("User", "deploy");
+        host
+    }
+
+    #[test]
+    fn test_command() {
+        let command = |template| Launcher::parse(template).unwrap().command(&host(), &[]);
+        assert_eq!(vec!["ssh", "web"], command("ssh {alias}"));
+        assert_eq!(vec!["ssh", "-A", "web"], command("ssh -A"));
+        assert_eq!(
+            vec!["mosh", "deploy@10.0.0.5"],
+            command("mosh {user}@{hostname}")
+        );
+        assert_eq!(
+            vec!["kitty", "+kitten", "ssh", "-p", "2222", "web"],
+            command("kitty +kitten ssh -p {port} {host}")
+        );
+        assert_eq!(
+            vec!["ssh", "-o", "RemoteCommand=tmux new -A", "web"],
+            command("ssh -o 'RemoteCommand=tmux new -A' {alias}")
+        );
+        assert_eq!(
+            vec!["et", "a b\"", "{1}", "web"],
+            command("et \"a b\\\"\" {1}")
+        );
+
+        let bare = Launcher::parse("ssh {alias}")
+            .unwrap()
+            .command(&Host::new("box"), &[]);
+        assert_eq!(vec!["ssh", "box"], bare);
+        assert_eq!("ssh -A", Launcher::parse("ssh -A").unwrap().to_string());
+    }
+
+    #[test]
+    fn test_bad_launcher() {
+        let err = |template| Launcher::parse(template).unwrap_err();
+        assert_eq!("empty command", err("  "));
+        assert_eq!("unknown placeholder: {hots}", err("ssh {hots}"));
+        assert_eq!("unterminated quote", err("ssh 'oops"));
+    }
+
+    #[test]
+    fn test_questions() {
+        let forward =
+            Launcher::parse("ssh -L {?Port}:localhost:{?Remote port=80} {alias} {?Port}").unwrap();
+        let questions = forward.questions();
+        assert_eq!(2, questions.len());
+        assert_eq!("Port", questions[0].label);
+        assert_eq!("80", questions[1].default);
+        assert_eq!(
+            vec!["ssh", "-L", "8080:localhost:8000", "web", "8080"],
+            forward.command(&host(), &["8080".into(), "8000".into()])
+        );
+
+        let run = Launcher::parse("ssh -t {alias} {?Command}").unwrap();
+        assert_eq!(
+            vec!["ssh", "-t", "web", "uptime -p"],
+            run.command(&host(), &["uptime -p".into()])
+        );
+        // a question doesn't count as a host placeholder
+        assert_eq!(
+            vec!["echo", "{?}", "hi", "web"],
+            Launcher::parse("echo {?} {?Say=hi}")
+                .unwrap()
+                .command(&host(), &[])
+        );
+
+        let actions = default_actions();
+        assert_eq!("sftp", actions.get_index(0).unwrap().0);
+        assert!(actions
+            .values()
+            .all(|a| a.words.iter().any(|w| w.contains("{alias}"))));
+    }
+
+    #[test]
+    fn test_shell_line() {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            "ssh -p 2222 deploy@10.0.0.5",
+            shell_line(&words(&["ssh", "-p", "2222", "deploy@10.0.0.5"]))
+        );
+        assert_eq!(
+            "ssh -t web 'tail -f' '' 'it'\\''s'",
+            shell_line(&words(&["ssh", "-t", "web", "tail -f", "", "it's"]))
+        );
+        let launcher = Launcher::parse("ssh -o 'RemoteCommand=tmux new -A'").unwrap();
+        assert_eq!(
+            "ssh -o 'RemoteCommand=tmux new -A' web",
+            shell_line(&launcher.command(&host(), &[]))
+        );
+    }
+
+    #[test]
+    fn test_term() {
+        assert_eq!(None, Term::Keep.choose(Some("xterm-kitty")));
+        assert_eq!(
+            Some("xterm".into()),
+            Term::parse("xterm").unwrap().choose(None)
+        );
+        assert_eq!(None, Term::Auto.choose(Some("tmux-256color")));
+        assert_eq!(None, Term::Auto.choose(None));
+        assert_eq!(
+            Some("xterm-256color".into()),
+            Term::Auto.choose(Some("xterm-kitty"))
+        );
+        assert_eq!("auto", Term::parse("auto").unwrap().to_string());
+    }
+}
@@ -1,6 +1,18 @@
 #[macro_use]
 pub mod color;
+pub mod editor;
+pub mod favorites;
+pub mod files;
+pub mod form;
+pub mod history;
+pub mod keymap;
+pub mod launch;
+pub mod menu;
+pub mod nav;
+pub mod search;
+pub mod settings;
 pub mod ssh_config;
+pub mod tty;
 pub mod tui;
 
 pub use tui::TUI as App;
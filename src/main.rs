@@ -1,11 +1,56 @@
 use {
-    shy::App,
-    std::{io, os::unix::process::CommandExt, panic, process::Command},
+    shy::{
+        color,
+        favorites::{favorites_path, Favorites},
+        history::{self, History},
+        launch::{shell_line, Launch},
+        search,
+        settings::{self, Settings},
+        ssh_config::{load_ssh_configs_lenient, local_user, Host, SshConfig},
+        tty,
+        tui::Mode,
+        App,
+    },
+    std::{
+        fmt, io,
+        os::unix::process::CommandExt,
+        panic,
+        path::Path,
+        process::{self, Command},
+    },
 };
 
+/// Exit statuses, so scripts can tell what went wrong.
+const EXIT_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_NO_HOST: i32 = 3;
+/// Quitting without picking a host in a `--print` mode, like fzf.
+const EXIT_CANCELLED: i32 = 130;
+
+/// What to do with the host that's picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    /// Run the launcher, or the action.
+    Launch,
+    /// Print its alias.
+    Alias,
+    /// Print the command that would have run.
+    Command,
+}
+
+/// What `shy list` can show after the alias.
+const COLUMNS: &[&str] = &["hostname", "user", "port", "comment", "file"];
+
 fn main() -> io::Result<()> {
-    let mut config_path = "~/.ssh/config";
+    let mut config_path = None;
     let mut search_mode = false;
+    let mut launcher = None;
+    let mut select_1 = false;
+    let mut exit_0 = false;
+    let mut output = Output::Launch;
+    let mut command = vec![];
+    // `--` came before any command, so the words are all a query
+    let mut literal = false;
 
     let args = parse_args()?;
     let mut args = args.iter();
@@ -14,69 +59,216 @@ fn main() -> io::Result<()> {
             "-h" | "-help" | "--help" => return print_usage(),
             "-v" | "-version" | "--version" => return print_version(),
             "-s" | "-search" | "--search" => search_mode = true,
+            "-1" | "-select-1" | "--select-1" => select_1 = true,
+            "-0" | "-exit-0" | "--exit-0" => exit_0 = true,
+            "-print" | "--print" => output = Output::Alias,
+            "-print-command" | "--print-command" => output = Output::Command,
             "-c" | "-config" | "--config" | "-F" => {
                 if let Some(path) = args.next() {
-                    config_path = path;
+                    config_path = Some(path.to_string());
                 } else {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Please provide a config path.",
-                    ));
+                    exit_with(EXIT_USAGE, "Please provide a config path.");
                 }
             }
+            "-l" | "-launcher" | "--launcher" => {
+                if let Some(template) = args.next() {
+                    launcher = Some(template.to_string());
+                } else {
+                    exit_with(EXIT_USAGE, "Please provide a launcher command.");
+                }
+            }
+            "--" => {
+                literal = command.is_empty();
+                command.extend(args.by_ref().map(String::as_str));
+            }
+            arg if !arg.starts_with('-') => command.push(arg),
+            _ => {}
+        }
+    }
+
+    // flags win over the config file
+    let path = settings::settings_path();
+    let mut settings = settings::load_settings(&path).unwrap_or_else(|e| exit_with(EXIT_ERROR, e));
+    if let Some(config_path) = config_path {
+        settings.ssh_config = vec![config_path];
+    }
+    if search_mode {
+        settings.mode = Mode::Search;
+    }
+    if let Some(template) = launcher {
+        settings.launcher = settings::parse_launcher(&template)
+            .unwrap_or_else(|e| exit_with(EXIT_USAGE, format!("--launcher: {}", e)));
+    }
+
+    let mut query = None;
+    match command.as_slice() {
+        [] => {}
+        // `shy -- list` searches for "list"
+        words if literal => query = Some(words.join(" ")),
+        ["config", "show"] => return print_settings(&path, &settings),
+        ["history"] => return print_history(),
+        ["list", columns @ ..] => return print_list(&settings, columns),
+        ["show", alias] => return print_host(&settings, alias),
+        ["connect", words @ ..] if !words.is_empty() => {
+            query = Some(words.join(" "));
+            select_1 = true;
+        }
+        ["config", ..] | ["show", ..] | ["connect", ..] => {
+            eprintln!("Usage: shy [list | show ALIAS | connect QUERY | config show | history]");
+            process::exit(EXIT_USAGE);
+        }
+        // anything else is a search
+        words => query = Some(words.join(" ")),
+    }
+
+    // see what the query finds before bothering with the TUI
+    if select_1 || exit_0 {
+        let config = load_config(&settings);
+        let found = match &query {
+            Some(query) if config.hosts.contains_key(query) => vec![query.as_str()],
+            Some(query) => search::find(&config, query),
+            None => config.hosts.keys().map(String::as_str).collect(),
+        };
+        match found.as_slice() {
+            [] if exit_0 => exit_with(
+                EXIT_NO_HOST,
+                format!("no hosts match {}", query.as_deref().unwrap_or("")),
+            ),
+            [alias] if select_1 => launch(
+                &settings,
+                output,
+                config.resolve(alias),
+                Launch::connect(alias),
+            ),
             _ => {}
         }
     }
 
-    match run(config_path, search_mode) {
+    match run(&settings, query.as_deref()) {
+        Ok(None) if output != Output::Launch => process::exit(EXIT_CANCELLED),
         Ok(None) => {}
         Err(e) => {
             if matches!(e.kind(), io::ErrorKind::NotFound) {
-                eprintln!("error: ~/.ssh/config not found");
+                eprintln!("error: {}", e);
             } else {
                 eprintln!("{}", e);
             }
-            std::process::exit(1);
-        }
-        Ok(Some(hostname)) => {
-            std::env::set_var("TERM", "xterm"); // TODO xterm-kitty hack
-            let mut cmd = Command::new("ssh");
-            let cmd = cmd.arg(hostname);
-            let err = cmd.exec();
-            eprintln!("{:?}", err);
+            process::exit(EXIT_ERROR);
         }
+        Ok(Some((host, how))) => launch(&settings, output, host, how),
     }
 
     Ok(())
 }
 
-/// Run the app, optionally returning a host to SSH to.
-fn run(config_path: &str, search_mode: bool) -> io::Result<Option<String>> {
+/// Run the app, optionally returning a host to SSH to and how.
+/// `query` starts it out searching.
+fn run(settings: &Settings, query: Option<&str>) -> io::Result<Option<(Host, Launch)>> {
     setup_panic_hook();
-    let mut app = App::new(config_path)?;
-    if search_mode {
-        app.mode = shy::tui::Mode::Search;
+    // losing track of past connections or favorites is no reason not
+    // to connect, and this has to be said before the TUI takes over the
+    // screen
+    let history = history::load_history(&history::history_path()).unwrap_or_else(|e| {
+        eprintln!("warning: can't read history: {}", e);
+        History::default()
+    });
+    let favorites = Favorites::load(&favorites_path()).unwrap_or_else(|e| {
+        eprintln!("warning: can't read favorites: {}", e);
+        Favorites::default()
+    });
+    let mut app = App::new(&settings.ssh_config, settings.match_exec)?;
+    app.mode = settings.mode.clone();
+    app.sort = settings.sort;
+    app.detail = settings.detail;
+    app.keymap = settings.keymap.clone();
+    app.history = history;
+    app.favorites = favorites;
+    app.actions = settings.actions.clone();
+    app.theme = if color::no_color() {
+        settings.theme.without_color()
+    } else {
+        settings.theme.clone()
+    };
+    if let Some(query) = query {
+        app.search_for(query);
+    }
+    let host = app
+        .run()
+        .map(|launch| launch.map(|launch| (app.resolve(&launch.alias), launch)));
+
+    // the terminal has to be restored before we can print anything
+    let warnings = app.warnings().to_vec();
+    drop(app);
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    host
+}
+
+/// Record the launch and replace ourselves with its command, or
+/// just print what we'd have run.
+fn launch(settings: &Settings, output: Output, host: Host, launch: Launch) -> ! {
+    let launcher = launch
+        .action
+        .and_then(|name| settings.actions.get(&name))
+        .unwrap_or(&settings.launcher);
+    let command = launcher.command(&host, &launch.answers);
+    match output {
+        Output::Alias => println!("{}", host.name),
+        Output::Command => println!("{}", shell_line(&command)),
+        Output::Launch => {}
+    }
+    if output != Output::Launch {
+        process::exit(0);
+    }
+
+    if let Err(e) = history::record(&history::history_path(), &host.name, history::now()) {
+        eprintln!("warning: can't save history: {}", e);
     }
-    Ok(app.run()?)
+    settings.term.apply();
+    let err = Command::new(&command[0]).args(&command[1..]).exec();
+    exit_with(EXIT_ERROR, format!("can't run {}: {}", command[0], err));
+}
+
+/// Print an error and exit with `status`.
+fn exit_with(status: i32, message: impl fmt::Display) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(status);
+}
+
+/// The ssh configs, for the commands that don't start the TUI. Lines
+/// we skipped are reported, but don't stop anything.
+fn load_config(settings: &Settings) -> SshConfig {
+    let mut config =
+        load_ssh_configs_lenient(&settings.ssh_config).unwrap_or_else(|e| exit_with(EXIT_ERROR, e));
+    config.match_exec = settings.match_exec;
+    for warning in &config.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    config
 }
 
 /// We need to cleanup the terminal before exiting, even on panic!
+/// The message goes to stderr, since stdout might be `--print`'s.
 fn setup_panic_hook() {
     panic::set_hook(Box::new(|panic_info| {
-        println!("{}", panic_info);
+        tty::reset();
+        eprintln!("{}", panic_info);
     }));
 }
 
-/// Converts -c=file into ["-c", "file"]
+/// Converts -c=file into ["-c", "file"]. Only the first `=` splits, so
+/// `--launcher='ssh -o ControlMaster=auto'` stays whole.
 fn parse_args() -> io::Result<Vec<String>> {
     let mut args = vec![];
     for arg in std::env::args().skip(1).collect::<Vec<String>>() {
-        if arg.starts_with('-') && arg.contains('=') {
-            for part in arg.split("=") {
-                args.push(part.to_string());
+        match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with('-') => {
+                args.push(flag.to_string());
+                args.push(value.to_string());
             }
-        } else {
-            args.push(arg);
+            _ => args.push(arg),
         }
     }
     Ok(args)
@@ -85,17 +277,118 @@ fn parse_args() -> io::Result<Vec<String>> {
 /// --help
 fn print_usage() -> io::Result<()> {
     println!(
-        "Usage: shy [options]
+        "Usage: shy [options] [QUERY]
+       shy [options] list [COLUMN...]
+       shy [options] show ALIAS
+       shy [options] connect QUERY
+       shy config show
+       shy history
 
 Options:
     -c, --config FILE    Use FILE instead of ~/.ssh/config
     -s, --search         Start in Search mode.
+    -l, --launcher CMD   Connect with CMD, like 'mosh {{user}}@{{hostname}}'
+    -1, --select-1       Connect right away if only one host matches.
+    -0, --exit-0         Exit right away if no hosts match.
+    --print              Print the host's alias instead of connecting.
+    --print-command      Print the command instead of running it.
     -v, --version        Print shy version and exit.
-    -h, --help           Show this message."
+    -h, --help           Show this message.
+    --                   Stop reading options: the rest is a QUERY, or
+                         a command's arguments.
+
+Commands:
+    list                 Print every host's alias, one to a line, with
+                         any of these columns after it, tab separated:
+                         hostname, user, port, comment, file.
+    show ALIAS           Print the settings ssh will use for ALIAS.
+    connect QUERY        Connect to the host QUERY matches, or search
+                         for it if it's not just one.
+    config show          Print the settings shy is using, from
+                         ~/.config/shy/config.toml and the options.
+    history              List past connections, newest first.
+
+Exit status:
+    0 on success, 1 on errors, 2 for bad usage, 3 for an unknown host
+    or nothing matching with --exit-0, and 130 for quitting without
+    picking a host with --print or --print-command.
+
+A QUERY starts shy in Search mode with it already typed in."
     );
     Ok(())
 }
 
+/// history
+fn print_history() -> io::Result<()> {
+    let history = history::load_history(&history::history_path())?;
+    for visit in history.visits.iter().rev() {
+        println!("{}  {}", history::format_time(visit.time), visit.alias);
+    }
+    Ok(())
+}
+
+/// list
+fn print_list(settings: &Settings, columns: &[&str]) -> io::Result<()> {
+    if let Some(column) = columns.iter().find(|c| !COLUMNS.contains(c)) {
+        exit_with(
+            EXIT_USAGE,
+            format!("unknown column: {} (try {})", column, COLUMNS.join(", ")),
+        );
+    }
+
+    let config = load_config(settings);
+    for (alias, host) in &config.hosts {
+        let resolved = config.resolve(alias);
+        let mut row = vec![alias.to_string()];
+        for column in columns {
+            row.push(match *column {
+                "hostname" => resolved.hostname().to_string(),
+                "user" => resolved.user().map_or_else(local_user, String::from),
+                "port" => resolved.port().unwrap_or("22").to_string(),
+                "comment" => host.comments.join(" "),
+                _ => host.file.display().to_string(),
+            });
+        }
+        println!("{}", row.join("\t"));
+    }
+    Ok(())
+}
+
+/// show ALIAS, like `ssh -G`: one lowercase keyword and value to a
+/// line, defaults included.
+fn print_host(settings: &Settings, alias: &str) -> io::Result<()> {
+    let config = load_config(settings);
+    if !config.hosts.contains_key(alias) {
+        exit_with(EXIT_NO_HOST, format!("no host named {}", alias));
+    }
+
+    let host = config.resolve(alias);
+    println!("host {}", alias);
+    println!("hostname {}", host.hostname());
+    println!("user {}", host.user().map_or_else(local_user, String::from));
+    println!("port {}", host.port().unwrap_or("22"));
+    for (key, values) in &host.options {
+        if ["hostname", "user", "port"].contains(&key.as_str()) {
+            continue;
+        }
+        for value in values {
+            println!("{} {}", key, value);
+        }
+    }
+    Ok(())
+}
+
+/// config show
+fn print_settings(path: &Path, settings: &Settings) -> io::Result<()> {
+    if path.exists() {
+        println!("# {}", path.display());
+    } else {
+        println!("# {} (not found)", path.display());
+    }
+    print!("{}", settings);
+    Ok(())
+}
+
 /// --version
 fn print_version() -> io::Result<()> {
     println!("shy v{}", shy::VERSION);
@@ -0,0 +1,200 @@
+//! The action menu: other things to do with a host than connect to
+//! it, like sftp or a port forward, and the questions some of them
+//! ask before they run.
+
+use {
+    crate::launch::{Actions, Launch, Question},
+    termion::event::Key,
+};
+
+/// What to do after a key press.
+#[derive(Debug, PartialEq)]
+pub enum MenuAction {
+    Continue,
+    Cancel,
+    Launch(Launch),
+}
+
+/// An open action menu.
+#[derive(Debug, Clone)]
+pub struct Menu {
+    pub alias: String,
+    /// Position in the actions.
+    pub selected: usize,
+    /// The picked action's questions, if it has any.
+    pub asking: Option<Answers>,
+}
+
+/// Answers to an action's questions, being typed in.
+#[derive(Debug, Clone)]
+pub struct Answers {
+    pub action: String,
+    pub questions: Vec<Question>,
+    /// One per question, starting as its default.
+    pub values: Vec<String>,
+    /// Which value has the cursor.
+    pub focus: usize,
+    /// Why the last try didn't run.
+    pub error: Option<String>,
+}
+
+impl Menu {
+    pub fn new(alias: &str) -> Menu {
+        Menu {
+            alias: alias.to_string(),
+            selected: 0,
+            asking: None,
+        }
+    }
+
+    /// Handle a key press.
+    pub fn update(&mut self, key: Key, actions: &Actions) -> MenuAction {
+        if let Some(answers) = &mut self.asking {
+            return match answers.update(key) {
+                // Esc goes back to the list
+                Some(false) => {
+                    self.asking = None;
+                    MenuAction::Continue
+                }
+                Some(true) => MenuAction::Launch(Launch {
+                    alias: self.alias.clone(),
+                    action: Some(answers.action.clone()),
+                    answers: answers.values.clone(),
+                }),
+                None => MenuAction::Continue,
+            };
+        }
+
+        let count = actions.len().max(1);
+        match key {
+            Key::Esc | Key::Ctrl('c') | Key::Char('q') => return MenuAction::Cancel,
+            Key::Char('\n') => return self.pick(self.selected, actions),
+            Key::Char(c @ '1'..='9') => {
+                return self.pick(c.to_digit(10).unwrap_or(1) as usize - 1, actions)
+            }
+            Key::Char('j') | Key::Down | Key::Ctrl('n') => {
+                self.selected = (self.selected + 1) % count;
+            }
+            Key::Char('k') | Key::Up | Key::Ctrl('p') => {
+                self.selected = (self.selected + count - 1) % count;
+            }
+            _ => {}
+        }
+        MenuAction::Continue
+    }
+
+    /// Run the action at `index`, or ask its questions first.
+    fn pick(&mut self, index: usize, actions: &Actions) -> MenuAction {
+        let (name, launcher) = match actions.get_index(index) {
+            Some(action) => action,
+            None => return MenuAction::Continue,
+        };
+        self.selected = index;
+        let questions = launcher.questions();
+        if questions.is_empty() {
+            return MenuAction::Launch(Launch {
+                alias: self.alias.clone(),
+                action: Some(name.clone()),
+                answers: vec![],
+            });
+        }
+        self.asking = Some(Answers {
+            action: name.clone(),
+            values: questions.iter().map(|q| q.default.clone()).collect(),
+            questions,
+            focus: 0,
+            error: None,
+        });
+        MenuAction::Continue
+    }
+}
+
+impl Answers {
+    /// Handle a key press. Returns whether to run, or None to keep
+    /// going.
+    fn update(&mut self, key: Key) -> Option<bool> {
+        let count = self.values.len();
+        match key {
+            Key::Esc | Key::Ctrl('c') => return Some(false),
+            Key::Char('\n') => match self.values.iter().position(|v| v.trim().is_empty()) {
+                Some(i) => {
+                    self.focus = i;
+                    self.error = Some(format!("{} can't be empty", self.questions[i].label));
+                }
+                None => return Some(true),
+            },
+            Key::Char('\t') | Key::Down | Key::Ctrl('n') => self.focus = (self.focus + 1) % count,
+            Key::BackTab | Key::Up | Key::Ctrl('p') => {
+                self.focus = (self.focus + count - 1) % count
+            }
+            Key::Backspace => {
+                self.values[self.focus].pop();
+            }
+            Key::Char(c) => self.values[self.focus].push(c),
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::launch::default_actions};
+
+    fn press(menu: &mut Menu, keys: &str) -> MenuAction {
+        let actions = default_actions();
+        let mut result = MenuAction::Continue;
+        for c in keys.chars() {
+            result = menu.update(Key::Char(c), &actions);
+        }
+        result
+    }
+
+    #[test]
+    fn test_menu() {
+        let mut menu = Menu::new("web");
+        assert_eq!(
+            MenuAction::Launch(Launch {
+                alias: "web".into(),
+                action: Some("sftp".into()),
+                answers: vec![],
+            }),
+            press(&mut menu, "\n")
+        );
+        assert_eq!(MenuAction::Continue, press(&mut menu, "jjj"));
+        assert_eq!(3, menu.selected);
+        assert_eq!(MenuAction::Cancel, press(&mut menu, "q"));
+
+        // socks asks for a port, starting at 1080
+        assert_eq!(MenuAction::Continue, press(&mut menu, "5"));
+        let asking = menu.asking.clone().unwrap();
+        assert_eq!("socks", asking.action);
+        assert_eq!(vec!["1080"], asking.values);
+        menu.update(Key::Backspace, &default_actions());
+        match press(&mut menu, "1\n") {
+            MenuAction::Launch(launch) => assert_eq!(vec!["1081"], launch.answers),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_answers() {
+        let mut menu = Menu::new("web");
+        press(&mut menu, "6");
+        assert_eq!(MenuAction::Continue, press(&mut menu, "\n"));
+        let asking = menu.asking.as_ref().unwrap();
+        assert_eq!(Some("Local port can't be empty".into()), asking.error);
+
+        press(&mut menu, "8080\t\t8000");
+        match press(&mut menu, "\n") {
+            MenuAction::Launch(launch) => {
+                assert_eq!(Some("forward".into()), launch.action);
+                assert_eq!(vec!["8080", "localhost", "8000"], launch.answers);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        menu.update(Key::Esc, &default_actions());
+        assert!(menu.asking.is_none());
+    }
+}
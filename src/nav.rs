@@ -0,0 +1,173 @@
+//! Where the movement keys take the selection. Just the sums, so they
+//! can be checked without a terminal.
+
+use crate::keymap::Action;
+
+/// A list of `len` hosts, `height` rows of it on screen from `offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Nav {
+    pub len: usize,
+    pub height: usize,
+    pub selected: usize,
+    pub offset: usize,
+}
+
+impl Nav {
+    /// The host `action` moves the selection to, or None if it doesn't
+    /// move it. `count` repeats it, stopping at either end. Without
+    /// one, Up and Down go round the ends when `wrap` is set.
+    pub fn target(&self, action: Action, count: Option<usize>, wrap: bool) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let last = self.len - 1;
+        let selected = self.selected.min(last);
+        // more than there are hosts never moves any further
+        let n = count.unwrap_or(1).clamp(1, self.len);
+        let height = self.height.max(1);
+        let down = |by: usize| selected.saturating_add(by).min(last);
+        let up = |by: usize| selected.saturating_sub(by);
+        // the rows on screen right now
+        let top = self.offset.min(last);
+        let bottom = (self.offset + height).min(self.len) - 1;
+
+        Some(match action {
+            // a single step wraps around, like the arrow keys always have
+            Action::Up if count.is_none() => match selected {
+                0 if wrap => last,
+                0 => return None,
+                i => i - 1,
+            },
+            Action::Down if count.is_none() => match selected {
+                i if i < last => i + 1,
+                _ if wrap => 0,
+                _ => return None,
+            },
+            Action::Up => up(n),
+            Action::Down => down(n),
+            Action::HalfPageDown => down(n.saturating_mul((height / 2).max(1))),
+            Action::HalfPageUp => up(n.saturating_mul((height / 2).max(1))),
+            Action::PageDown => down(n.saturating_mul(height)),
+            Action::PageUp => up(n.saturating_mul(height)),
+            Action::First => count.map_or(0, |c| c.saturating_sub(1)).min(last),
+            Action::Last => count.map_or(last, |c| c.saturating_sub(1)).min(last),
+            Action::Top => (top + n - 1).min(bottom),
+            Action::Middle => top + (bottom - top) / 2,
+            Action::Bottom => bottom.saturating_sub(n - 1).max(top),
+            _ => return None,
+        })
+    }
+
+    /// The offset that puts host `i` on screen, scrolling as little as
+    /// it can.
+    pub fn scroll(&self, i: usize) -> usize {
+        let height = self.height.max(1);
+        if i < self.offset {
+            i
+        } else if i >= self.offset + height {
+            i + 1 - height
+        } else {
+            self.offset
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nav(len: usize, selected: usize, offset: usize) -> Nav {
+        Nav {
+            len,
+            height: 10,
+            selected,
+            offset,
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let nav = nav(0, 0, 0);
+        for &action in &[
+            Action::Up,
+            Action::Down,
+            Action::PageDown,
+            Action::Last,
+            Action::Bottom,
+        ] {
+            assert_eq!(None, nav.target(action, None, true));
+            assert_eq!(None, nav.target(action, Some(3), true));
+        }
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(Some(4), nav(5, 0, 0).target(Action::Up, None, true));
+        assert_eq!(Some(0), nav(5, 4, 0).target(Action::Down, None, true));
+        assert_eq!(None, nav(5, 0, 0).target(Action::Up, None, false));
+        assert_eq!(None, nav(5, 4, 0).target(Action::Down, None, false));
+        assert_eq!(Some(3), nav(5, 4, 0).target(Action::Up, None, false));
+        // counts stop at the ends instead
+        assert_eq!(Some(0), nav(5, 1, 0).target(Action::Up, Some(3), true));
+        assert_eq!(Some(4), nav(5, 3, 0).target(Action::Down, Some(3), true));
+    }
+
+    #[test]
+    fn test_counts_past_the_end() {
+        let nav = nav(50, 20, 15);
+        assert_eq!(Some(49), nav.target(Action::Down, Some(usize::MAX), false));
+        assert_eq!(Some(0), nav.target(Action::Up, Some(usize::MAX), false));
+        assert_eq!(
+            Some(49),
+            nav.target(Action::PageDown, Some(usize::MAX), false)
+        );
+        assert_eq!(
+            Some(0),
+            nav.target(Action::HalfPageUp, Some(usize::MAX), false)
+        );
+        assert_eq!(Some(49), nav.target(Action::First, Some(1000), false));
+        assert_eq!(Some(9), nav.target(Action::Last, Some(10), false));
+        assert_eq!(Some(24), nav.target(Action::Top, Some(1000), false));
+        assert_eq!(Some(15), nav.target(Action::Bottom, Some(1000), false));
+    }
+
+    #[test]
+    fn test_pages() {
+        let nav = nav(50, 20, 15);
+        assert_eq!(Some(30), nav.target(Action::PageDown, None, false));
+        assert_eq!(Some(0), nav.target(Action::PageUp, Some(2), false));
+        assert_eq!(Some(25), nav.target(Action::HalfPageDown, None, false));
+        assert_eq!(Some(10), nav.target(Action::HalfPageUp, Some(2), false));
+        assert_eq!(Some(0), nav.target(Action::First, None, false));
+        assert_eq!(Some(49), nav.target(Action::Last, None, false));
+        assert_eq!(Some(15), nav.target(Action::Top, None, false));
+        assert_eq!(Some(17), nav.target(Action::Top, Some(3), false));
+        assert_eq!(Some(19), nav.target(Action::Middle, None, false));
+        assert_eq!(Some(24), nav.target(Action::Bottom, None, false));
+        assert_eq!(Some(22), nav.target(Action::Bottom, Some(3), false));
+    }
+
+    #[test]
+    fn test_shorter_than_the_screen() {
+        let nav = nav(4, 1, 0);
+        assert_eq!(Some(3), nav.target(Action::PageDown, None, false));
+        assert_eq!(Some(3), nav.target(Action::HalfPageDown, None, false));
+        assert_eq!(Some(0), nav.target(Action::Top, None, false));
+        assert_eq!(Some(1), nav.target(Action::Middle, None, false));
+        assert_eq!(Some(3), nav.target(Action::Bottom, None, false));
+        assert_eq!(Some(3), nav.target(Action::Top, Some(9), false));
+        assert_eq!(Some(0), nav.target(Action::Bottom, Some(9), false));
+        assert_eq!(0, nav.scroll(3));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let nav = nav(50, 20, 15);
+        assert_eq!(15, nav.scroll(20));
+        assert_eq!(15, nav.scroll(24));
+        assert_eq!(16, nav.scroll(25));
+        assert_eq!(40, nav.scroll(49));
+        assert_eq!(3, nav.scroll(3));
+        assert_eq!(0, nav.scroll(0));
+    }
+}
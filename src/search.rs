@@ -0,0 +1,425 @@
+//! Search queries. Plain terms fuzzy match a host's alias, HostName,
+//! User or comments; `alias:`, `host:`, `user:`, `port:` and
+//! `comment:` look at one field; and `!term` rules hosts out.
+
+use {
+    crate::ssh_config::{Host, SshConfig},
+    fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher},
+    std::{cmp::Reverse, fmt},
+};
+
+/// A part of a host that search looks at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Alias,
+    HostName,
+    User,
+    Port,
+    Comment,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Field::Alias => "Host",
+            Field::HostName => "HostName",
+            Field::User => "User",
+            Field::Port => "Port",
+            Field::Comment => "Comment",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The searchable parts of one host, inherited settings included.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub alias: String,
+    pub hostname: String,
+    pub user: String,
+    pub port: String,
+    pub comments: Vec<String>,
+}
+
+impl Entry {
+    /// Build an entry from a host, which should already be resolved.
+    pub fn new(host: &Host) -> Entry {
+        Entry {
+            alias: host.name.clone(),
+            hostname: host.hostname().to_string(),
+            user: host.user().unwrap_or("").to_string(),
+            port: host.port().unwrap_or("").to_string(),
+            comments: host.comments.clone(),
+        }
+    }
+
+    /// Every value of `field`.
+    fn values(&self, field: Field) -> Vec<&str> {
+        match field {
+            Field::Alias => vec![&self.alias],
+            Field::HostName => vec![&self.hostname],
+            Field::User => vec![&self.user],
+            Field::Port => vec![&self.port],
+            Field::Comment => self.comments.iter().map(|c| c.as_ref()).collect(),
+        }
+    }
+}
+
+/// How a host matched a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    /// Higher is better.
+    pub score: i64,
+    /// The field the best term matched, and its value.
+    pub field: Field,
+    pub value: String,
+    /// Char indexes of the matched chars in `value`.
+    pub indices: Vec<usize>,
+    /// Char indexes of every matched char in the alias.
+    pub alias_indices: Vec<usize>,
+}
+
+/// One word of a query.
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    negated: bool,
+    /// Only look at this field. Plain terms look at all but Port.
+    field: Option<Field>,
+    text: String,
+}
+
+/// The fields a plain term looks at, best first.
+const PLAIN_FIELDS: &[Field] = &[Field::Alias, Field::HostName, Field::User, Field::Comment];
+
+/// A parsed search input.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+impl Query {
+    /// Parse what's been typed into the prompt. Half-typed terms like
+    /// `user:` or `!` are ignored so the list doesn't empty out while
+    /// you type them.
+    pub fn parse(input: &str) -> Query {
+        let mut terms = vec![];
+        for word in input.split_whitespace() {
+            let (negated, word) = match word.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, word),
+            };
+            let (field, text) = match word.split_once(':') {
+                Some((name, text)) => match qualifier(name) {
+                    Some(field) => (Some(field), text),
+                    None => (None, word),
+                },
+                None => (None, word),
+            };
+            if !text.is_empty() {
+                terms.push(Term {
+                    negated,
+                    field,
+                    text: text.to_string(),
+                });
+            }
+        }
+        Query { terms }
+    }
+
+    /// Is there nothing to search for?
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Does `entry` match every term? The score adds up the fuzzy
+    /// scores of the plain terms.
+    pub fn matches(&self, matcher: &SkimMatcherV2, entry: &Entry) -> Option<Match> {
+        let mut best: Option<Match> = None;
+        let mut score = 0;
+        let mut alias_indices = vec![];
+
+        for term in &self.terms {
+            let found = term.find(matcher, entry);
+            if term.negated {
+                if found.is_some() {
+                    return None;
+                }
+                continue;
+            }
+
+            let found = found?;
+            score += found.score;
+            if found.field == Field::Alias {
+                alias_indices.extend(&found.indices);
+            }
+            if best.as_ref().is_none_or(|b| found.score > b.score) {
+                best = Some(found);
+            }
+        }
+
+        let mut found = best.unwrap_or_else(|| Match {
+            score: 0,
+            field: Field::Alias,
+            value: entry.alias.clone(),
+            indices: vec![],
+            alias_indices: vec![],
+        });
+        alias_indices.sort_unstable();
+        alias_indices.dedup();
+        found.score = score;
+        found.alias_indices = alias_indices;
+        Some(found)
+    }
+}
+
+impl Term {
+    /// The best match for this term in `entry`, ignoring negation.
+    fn find(&self, matcher: &SkimMatcherV2, entry: &Entry) -> Option<Match> {
+        let fields = match self.field {
+            Some(field) => vec![field],
+            None => PLAIN_FIELDS.to_vec(),
+        };
+
+        let mut best: Option<Match> = None;
+        for field in fields {
+            for value in entry.values(field) {
+                let found = match (self.field, field) {
+                    // qualified hostnames and users are looked up as
+                    // written, so `host:10.0.` means what it says
+                    (Some(_), Field::HostName) | (Some(_), Field::User) => {
+                        substring(value, &self.text).map(|indices| (0, indices))
+                    }
+                    (_, Field::Port) => {
+                        if value == self.text {
+                            Some((0, (0..value.chars().count()).collect()))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => matcher.fuzzy_indices(value, &self.text),
+                };
+                if let Some((score, indices)) = found {
+                    if best.as_ref().is_none_or(|b| score > b.score) {
+                        best = Some(Match {
+                            score,
+                            field,
+                            value: value.to_string(),
+                            indices,
+                            alias_indices: vec![],
+                        });
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+/// The field a `name:` qualifier refers to.
+fn qualifier(name: &str) -> Option<Field> {
+    match name.to_lowercase().as_ref() {
+        "alias" => Some(Field::Alias),
+        "host" | "hostname" => Some(Field::HostName),
+        "user" => Some(Field::User),
+        "port" => Some(Field::Port),
+        "comment" => Some(Field::Comment),
+        _ => None,
+    }
+}
+
+/// Char indexes of the first case-insensitive occurrence of `needle`
+/// in `haystack`.
+fn substring(haystack: &str, needle: &str) -> Option<Vec<usize>> {
+    let haystack = haystack.chars().collect::<Vec<_>>();
+    let needle = needle.chars().collect::<Vec<_>>();
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    (0..=haystack.len() - needle.len())
+        .find(|&start| {
+            haystack[start..start + needle.len()]
+                .iter()
+                .zip(&needle)
+                .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+        })
+        .map(|start| (start..start + needle.len()).collect())
+}
+
+/// What search looks at for every host in `config`.
+pub fn entries(config: &SshConfig) -> Vec<Entry> {
+    config
+        .hosts
+        .keys()
+        .map(|alias| Entry::new(&config.resolve(alias)))
+        .collect()
+}
+
+/// Indexes of the `entries` that match `query`, best match first and
+/// ties in the same order as `order`. `boosts` are added to the
+/// scores of the entries at the same index.
+pub fn rank(
+    matcher: &SkimMatcherV2,
+    entries: &[Entry],
+    order: &[usize],
+    boosts: &[i64],
+    query: &Query,
+) -> Vec<usize> {
+    let mut scored = order
+        .iter()
+        .filter_map(|&i| {
+            let boost = boosts.get(i).cloned().unwrap_or(0);
+            query
+                .matches(matcher, &entries[i])
+                .map(|found| (Reverse(found.score + boost), i))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|&(score, _)| score);
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Aliases of the hosts in `config` that match `input`, best first.
+pub fn find<'a>(config: &'a SshConfig, input: &str) -> Vec<&'a str> {
+    let entries = entries(config);
+    let order = (0..entries.len()).collect::<Vec<_>>();
+    let matcher = SkimMatcherV2::default();
+    rank(&matcher, &entries, &order, &[], &Query::parse(input))
+        .into_iter()
+        .filter_map(|i| config.hosts.get_index(i).map(|(alias, _)| alias.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ssh_config::load_ssh_config};
+
+    fn config() -> SshConfig {
+        load_ssh_config("./tests/test_config").expect("failed to parse config")
+    }
+
+    fn entries() -> Vec<Entry> {
+        super::entries(&config())
+    }
+
+    /// Aliases of the entries that match `input`, in config order.
+    fn search(input: &str) -> Vec<String> {
+        let matcher = SkimMatcherV2::default();
+        let query = Query::parse(input);
+        entries()
+            .into_iter()
+            .filter(|e| query.matches(&matcher, e).is_some())
+            .map(|e| e.alias)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse("web !user:root  port: ! host:10.0. ipv6:beef");
+        assert_eq!(
+            vec![
+                (false, None, "web"),
+                (true, Some(Field::User), "root"),
+                (false, Some(Field::HostName), "10.0."),
+                (false, None, "ipv6:beef"),
+            ],
+            query
+                .terms
+                .iter()
+                .map(|t| (t.negated, t.field, t.text.as_ref()))
+                .collect::<Vec<_>>()
+        );
+        assert!(Query::parse(" user: ").is_empty());
+    }
+
+    #[test]
+    fn test_fields() {
+        let matcher = SkimMatcherV2::default();
+        let entries = entries();
+        let nas01 = entries.iter().find(|e| e.alias == "nas01").unwrap();
+
+        let found = Query::parse("168.1").matches(&matcher, nas01).unwrap();
+        assert_eq!(Field::HostName, found.field);
+        assert_eq!("192.168.1.100", found.value);
+        assert!(found.alias_indices.is_empty());
+
+        let found = Query::parse("another").matches(&matcher, nas01).unwrap();
+        assert_eq!(Field::Comment, found.field);
+
+        let found = Query::parse("nas").matches(&matcher, nas01).unwrap();
+        assert_eq!(Field::Alias, found.field);
+        assert_eq!(vec![0, 1, 2], found.alias_indices);
+    }
+
+    #[test]
+    fn test_qualifiers() {
+        assert_eq!(vec!["nas01"], search("user:root"));
+        assert_eq!(
+            vec!["nas01", "uk.gw.lan", "uk.lan"],
+            search("host:192.168.")
+        );
+        assert_eq!(vec!["nixcraft"], search("port:4242"));
+        assert!(search("port:42").is_empty());
+        assert_eq!(
+            vec!["ec2-some-long-name.amazon.probably.com"],
+            search("ec2 !host:ec2-2")
+        );
+        assert_eq!(vec!["midi-files.com"], search("user:MIDI"));
+    }
+
+    #[test]
+    fn test_substring() {
+        assert_eq!(Some(vec![1, 2]), substring("bücher", "ÜC"));
+        assert_eq!(None, substring("b", "bb"));
+        assert_eq!(Some(vec![]), substring("b", ""));
+    }
+
+    #[test]
+    fn test_rank() {
+        let matcher = SkimMatcherV2::default();
+        let entries = |aliases: &[&str]| {
+            aliases
+                .iter()
+                .map(|alias| Entry {
+                    alias: alias.to_string(),
+                    ..Entry::default()
+                })
+                .collect::<Vec<_>>()
+        };
+        let rank = |entries: &[Entry], input| {
+            let order = (0..entries.len()).collect::<Vec<_>>();
+            rank(&matcher, entries, &order, &[], &Query::parse(input))
+        };
+
+        // a tight match beats a scattered one, and misses are dropped
+        assert_eq!(
+            vec![2, 0],
+            rank(&entries(&["n-a-s-box", "web", "nas01"]), "nas")
+        );
+
+        // equal scores keep config order
+        let boxes = entries(&["box1", "box2"]);
+        assert_eq!(vec![0, 1], rank(&boxes, "box"));
+        assert!(rank(&boxes, "zzz").is_empty());
+
+        // or whatever order they're sorted in
+        let order = [1, 0];
+        assert_eq!(
+            vec![1, 0],
+            super::rank(&matcher, &boxes, &order, &[], &Query::parse("box"))
+        );
+
+        // a boost settles ties
+        let boosts = [0, 5];
+        assert_eq!(
+            vec![1, 0],
+            super::rank(&matcher, &boxes, &[0, 1], &boosts, &Query::parse("box"))
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        let config = config();
+        assert_eq!(vec!["nas01"], find(&config, "nas01"));
+        assert_eq!(vec!["uk.gw.lan", "uk.lan"], find(&config, "uk"));
+        assert!(find(&config, "zzzz").is_empty());
+    }
+}
@@ -0,0 +1,447 @@
+//! shy's own config file, `~/.config/shy/config.toml`. Everything in
+//! it is optional, and command line flags win over it:
+//!
+//! ```toml
+//! ssh_config = ["~/.ssh/config", "~/work/ssh_config"]
+//! mode = "search"
+//! sort = "frecency"
+//! detail = false
+//! launcher = "mosh {user}@{hostname}"
+//! term = "auto"
+//! theme = "light"
+//! match_exec = true
+//!
+//! [colors]
+//! selected = "#ff8700 bold"
+//!
+//! [actions]
+//! rsync = "rsync -av {?Local dir} {alias}:{?Remote dir}"
+//! ssh-copy-id = "none"
+//!
+//! [keys.nav]
+//! x = "delete-host"
+//! d = "none"
+//!
+//! [keys.search]
+//! ctrl-j = "down"
+//! "j k" = "cancel"
+//! ```
+
+use {
+    crate::{
+        color::{Style, Theme, PRESETS},
+        keymap::{keys_name, KeyMode, Keymap},
+        launch::{default_actions, Actions, Launcher, Term},
+        tui::{Mode, Sort},
+    },
+    std::{
+        env, fmt, fs, io,
+        path::{Path, PathBuf},
+    },
+    toml::{value::Table, Value},
+};
+
+/// Everything in the config file, or the defaults for what isn't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// ssh configs to read, in order. New hosts go in the first one.
+    pub ssh_config: Vec<String>,
+    /// Nav or Search.
+    pub mode: Mode,
+    pub sort: Sort,
+    /// Show the detail pane?
+    pub detail: bool,
+    /// The command to connect with.
+    pub launcher: Launcher,
+    /// What to do with TERM when connecting.
+    pub term: Term,
+    /// The action menu.
+    pub actions: Actions,
+    pub theme: Theme,
+    pub keymap: Keymap,
+    /// Run `Match exec` commands when working out a host's settings?
+    pub match_exec: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            ssh_config: vec!["~/.ssh/config".into()],
+            mode: Mode::Nav,
+            sort: Sort::Config,
+            detail: true,
+            launcher: Launcher::default(),
+            term: Term::Auto,
+            actions: default_actions(),
+            theme: Theme::default(),
+            keymap: Keymap::default(),
+            match_exec: false,
+        }
+    }
+}
+
+/// Where the config file lives: `$SHY_CONFIG` if it's set, otherwise
+/// `$XDG_CONFIG_HOME/shy/config.toml` or `~/.config/shy/config.toml`.
+pub fn settings_path() -> PathBuf {
+    let var = |name| env::var_os(name).filter(|v| !v.is_empty());
+    if let Some(path) = var("SHY_CONFIG") {
+        return PathBuf::from(path);
+    }
+    let dir = var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    dir.join("shy").join("config.toml")
+}
+
+/// Load the config file at `path`. It's fine for it not to exist.
+pub fn load_settings(path: &Path) -> io::Result<Settings> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Settings::default()),
+        Err(e) => return Err(e),
+    };
+    parse_settings(&text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Parse the text of a config file.
+pub fn parse_settings(text: &str) -> Result<Settings, String> {
+    let value = text.parse::<Value>().map_err(|e| e.to_string())?;
+    let mut settings = Settings::default();
+
+    // the preset goes first, so [colors] can change it
+    if let Some(theme) = value.get("theme") {
+        let name = string(theme, "theme")?;
+        settings.theme =
+            Theme::preset(name).ok_or(format!("theme should be one of: {}", PRESETS.join(", ")))?;
+    }
+
+    for (name, value) in table(&value, "")? {
+        match name.as_ref() {
+            "ssh_config" => {
+                settings.ssh_config = match value {
+                    Value::String(path) => vec![path.clone()],
+                    Value::Array(paths) if !paths.is_empty() => paths
+                        .iter()
+                        .map(|p| p.as_str().map(String::from))
+                        .collect::<Option<_>>()
+                        .ok_or("ssh_config should be a list of paths")?,
+                    _ => return Err("ssh_config should be a path or a list of paths".into()),
+                }
+            }
+            "mode" => {
+                settings.mode = match string(value, name)? {
+                    "nav" => Mode::Nav,
+                    "search" => Mode::Search,
+                    _ => return Err("mode should be \"nav\" or \"search\"".into()),
+                }
+            }
+            "sort" => {
+                settings.sort = match string(value, name)? {
+                    "config" => Sort::Config,
+                    "alpha" => Sort::Alpha,
+                    "frecency" => Sort::Frecency,
+                    _ => return Err("sort should be \"config\", \"alpha\" or \"frecency\"".into()),
+                }
+            }
+            "detail" => {
+                settings.detail = value.as_bool().ok_or("detail should be true or false")?
+            }
+            "launcher" => {
+                settings.launcher =
+                    parse_launcher(string(value, name)?).map_err(|e| format!("launcher: {}", e))?
+            }
+            "term" => settings.term = Term::parse(string(value, name)?)?,
+            "match_exec" => {
+                settings.match_exec = value
+                    .as_bool()
+                    .ok_or("match_exec should be true or false")?
+            }
+            "theme" => {}
+            "colors" => {
+                for (role, style) in table(value, name)? {
+                    let style = Style::parse(string(style, role)?)
+                        .map_err(|e| format!("colors.{}: {}", role, e))?;
+                    settings.theme.set(role, style)?;
+                }
+            }
+            "actions" => {
+                for (action, template) in table(value, name)? {
+                    let template = string(template, &format!("actions.{}", action))?;
+                    if template == "none" {
+                        settings.actions.shift_remove(action);
+                        continue;
+                    }
+                    let launcher = Launcher::parse(template)
+                        .map_err(|e| format!("actions.{}: {}", action, e))?;
+                    settings.actions.insert(action.clone(), launcher);
+                }
+            }
+            "keys" => parse_keys(&mut settings.keymap, value)?,
+            _ => return Err(format!("unknown setting: {}", name)),
+        }
+    }
+    Ok(settings)
+}
+
+/// A launcher template. It runs straight from Enter, so it can't ask
+/// questions like an action can.
+pub fn parse_launcher(template: &str) -> Result<Launcher, String> {
+    let launcher = Launcher::parse(template)?;
+    if !launcher.questions().is_empty() {
+        return Err("only actions can ask questions".into());
+    }
+    Ok(launcher)
+}
+
+/// The `[keys.nav]` and `[keys.search]` tables.
+fn parse_keys(keymap: &mut Keymap, value: &Value) -> Result<(), String> {
+    for (name, bindings) in table(value, "keys")? {
+        let mode = match name.as_ref() {
+            "nav" => KeyMode::Nav,
+            "search" => KeyMode::Search,
+            _ => return Err(format!("unknown key mode: keys.{}", name)),
+        };
+        for (keys, action) in table(bindings, &format!("keys.{}", name))? {
+            let action = action.as_str().ok_or(format!(
+                "keys.{}: \"{}\" should be an action name",
+                name, keys
+            ))?;
+            keymap
+                .bind(mode, keys, action)
+                .map_err(|e| format!("keys.{}: {}", name, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// `value` as a table, or an error naming it.
+fn table<'a>(value: &'a Value, name: &str) -> Result<&'a Table, String> {
+    value
+        .as_table()
+        .ok_or(format!("{} should be a table", name))
+}
+
+/// `value` as a string, or an error naming it.
+fn string<'a>(value: &'a Value, name: &str) -> Result<&'a str, String> {
+    value.as_str().ok_or(format!("{} should be a string", name))
+}
+
+/// The settings as a config file, every key binding included.
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut root = Table::new();
+        let paths = self.ssh_config.iter().cloned().map(Value::String);
+        root.insert("ssh_config".into(), Value::Array(paths.collect()));
+        let mode = if self.mode == Mode::Search {
+            "search"
+        } else {
+            "nav"
+        };
+        root.insert("mode".into(), Value::String(mode.into()));
+        let sort = match self.sort {
+            Sort::Config => "config",
+            Sort::Alpha => "alpha",
+            Sort::Frecency => "frecency",
+        };
+        root.insert("sort".into(), Value::String(sort.into()));
+        root.insert("detail".into(), Value::Boolean(self.detail));
+        root.insert("launcher".into(), Value::String(self.launcher.to_string()));
+        root.insert("term".into(), Value::String(self.term.to_string()));
+        root.insert("theme".into(), Value::String(self.theme.name.clone()));
+        root.insert("match_exec".into(), Value::Boolean(self.match_exec));
+
+        let actions = self
+            .actions
+            .iter()
+            .map(|(name, launcher)| (name.clone(), Value::String(launcher.to_string())))
+            .collect();
+        root.insert("actions".into(), Value::Table(actions));
+
+        let colors = self
+            .theme
+            .roles()
+            .into_iter()
+            .map(|(role, style)| (role.to_string(), Value::String(style.to_string())))
+            .collect();
+        root.insert("colors".into(), Value::Table(colors));
+
+        let mut keys = Table::new();
+        for mode in &[KeyMode::Nav, KeyMode::Search] {
+            let bindings = self
+                .keymap
+                .bindings(*mode)
+                .iter()
+                .map(|(k, action)| (keys_name(k), Value::String(action.to_string())))
+                .collect();
+            keys.insert(mode.name().into(), Value::Table(bindings));
+        }
+        root.insert("keys".into(), Value::Table(keys));
+
+        write!(f, "{}", Value::Table(root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::keymap::{parse_keys, Action, Lookup},
+    };
+
+    #[test]
+    fn test_parse_settings() {
+        let settings = parse_settings(
+            r#"
+            [keys.nav]
+            x = "delete-host"
+            d = "none"
+
+            [keys.search]
+            ctrl-j = "down"
+            "j k" = "cancel"
+            "#,
+        )
+        .unwrap();
+        let lookup = |mode, keys| settings.keymap.lookup(mode, &parse_keys(keys).unwrap());
+        assert_eq!(Lookup::Found(Action::DeleteHost), lookup(KeyMode::Nav, "x"));
+        assert_eq!(Lookup::Unbound, lookup(KeyMode::Nav, "d"));
+        assert_eq!(
+            Lookup::Found(Action::Down),
+            lookup(KeyMode::Search, "ctrl-j")
+        );
+        assert_eq!(
+            Lookup::Found(Action::Cancel),
+            lookup(KeyMode::Search, "j k")
+        );
+
+        assert_eq!(Ok(Settings::default()), parse_settings(""));
+    }
+
+    #[test]
+    fn test_options() {
+        let settings = parse_settings(
+            r#"
+            ssh_config = ["~/.ssh/config", "/etc/ssh/ssh_config"]
+            mode = "search"
+            sort = "alpha"
+            detail = false
+            launcher = "mosh {hostname}"
+            term = "keep"
+            match_exec = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(2, settings.ssh_config.len());
+        assert_eq!(Mode::Search, settings.mode);
+        assert_eq!(Sort::Alpha, settings.sort);
+        assert!(!settings.detail);
+        assert_eq!("mosh {hostname}", settings.launcher.to_string());
+        assert_eq!(Term::Keep, settings.term);
+        assert!(settings.match_exec);
+
+        let settings = parse_settings("ssh_config = \"~/other\"").unwrap();
+        assert_eq!(vec!["~/other"], settings.ssh_config);
+    }
+
+    #[test]
+    fn test_actions() {
+        let settings =
+            parse_settings("[actions]\nsftp = \"none\"\nrsync = \"rsync {?Dir} {alias}:\"")
+                .unwrap();
+        assert!(!settings.actions.contains_key("sftp"));
+        assert_eq!("scp", settings.actions.get_index(0).unwrap().0);
+        assert_eq!(
+            "rsync {?Dir} {alias}:",
+            settings.actions["rsync"].to_string()
+        );
+    }
+
+    #[test]
+    fn test_actions_order() {
+        let settings =
+            parse_settings("[actions]\nzz-last = \"zz {alias}\"\naa-first = \"aa {alias}\"")
+                .unwrap();
+        let order = vec![
+            "sftp",
+            "scp",
+            "mosh",
+            "ssh-copy-id",
+            "socks",
+            "forward",
+            "run",
+            "zz-last",
+            "aa-first",
+        ];
+        assert_eq!(order, settings.actions.keys().collect::<Vec<_>>());
+
+        // and `config show` writes them back in the same order
+        let shown = parse_settings(&settings.to_string()).unwrap();
+        assert_eq!(order, shown.actions.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_theme() {
+        let settings = parse_settings("theme = \"light\"\n[colors]\nmatch = \"208 bold\"").unwrap();
+        let light = Theme::preset("light").unwrap();
+        assert_eq!("light", settings.theme.name);
+        assert_eq!(light.host, settings.theme.host);
+        assert_eq!("208 bold", settings.theme.matched.to_string());
+    }
+
+    #[test]
+    fn test_show() {
+        let mut settings =
+            parse_settings("sort = \"alpha\"\n[keys.nav]\n\"g h\" = \"first\"").unwrap();
+        settings.ssh_config = vec!["/tmp/config".into()];
+        let shown = settings.to_string();
+        assert!(shown.contains("[keys.nav]"));
+        assert!(shown.contains("\"g h\" = \"first\""));
+        assert_eq!(Ok(settings), parse_settings(&shown));
+    }
+
+    #[test]
+    fn test_bad_settings() {
+        let err = |text| parse_settings(text).unwrap_err();
+        assert_eq!("unknown setting: colour", err("colour = 1"));
+        assert_eq!("unknown key mode: keys.form", err("[keys.form]"));
+        assert_eq!(
+            "keys.nav: unknown action: launch",
+            err("[keys.nav]\nx = \"launch\"")
+        );
+        assert_eq!(
+            "keys.nav: unknown key: meta-x",
+            err("[keys.nav]\nmeta-x = \"quit\"")
+        );
+        assert!(err("[keys.nav]\nx = 1").contains("should be an action"));
+        assert!(err("keys = [").contains("line 1"));
+        assert_eq!(
+            "mode should be \"nav\" or \"search\"",
+            err("mode = \"edit\"")
+        );
+        assert_eq!("detail should be true or false", err("detail = \"yes\""));
+        assert_eq!("match_exec should be true or false", err("match_exec = 1"));
+        assert!(err("ssh_config = []").starts_with("ssh_config should be"));
+        assert_eq!("launcher: empty command", err("launcher = \" \""));
+        assert_eq!(
+            "launcher: only actions can ask questions",
+            err("launcher = \"ssh {?Port} {alias}\"")
+        );
+        assert_eq!(
+            "actions.x: unknown placeholder: {hots}",
+            err("[actions]\nx = \"ssh {hots}\"")
+        );
+        assert_eq!("term can't be empty", err("term = \"\""));
+        assert!(err("theme = \"dark\"").starts_with("theme should be one of"));
+        assert_eq!("unknown color role: rows", err("[colors]\nrows = \"red\""));
+        assert_eq!(
+            "colors.host: unknown color: mauve",
+            err("[colors]\nhost = \"mauve\"")
+        );
+    }
+}
@@ -1,103 +1,979 @@
+pub mod document;
+
 use {
     indexmap::IndexMap,
-    std::{env, fs, io},
+    std::{
+        env, error, fmt, fs, io,
+        ops::Range,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    },
 };
 
-pub type HostMap = IndexMap<String, String>;
+/// Concrete hosts, keyed by their alias, in config order.
+pub type HostMap = IndexMap<String, Host>;
+
+/// Directives keyed by their lowercased keyword, in the order they
+/// first appear. Like ssh, most keywords only keep their first value;
+/// the ones in `MULTI_VALUED` keep every value.
+pub type Options = IndexMap<String, Vec<String>>;
+
+/// Keywords ssh lets you repeat, accumulating every value.
+const MULTI_VALUED: &[&str] = &[
+    "certificatefile",
+    "dynamicforward",
+    "identityfile",
+    "localforward",
+    "remoteforward",
+    "sendenv",
+];
+
+/// A `Host` stanza and every directive under it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Host {
+    pub name: String,
+    /// Every pattern on the stanza's Host line, this alias included.
+    pub patterns: Vec<String>,
+    pub options: Options,
+    /// The config file the stanza came from.
+    pub file: PathBuf,
+    /// Comment lines right above the Host line, without their `#`.
+    pub comments: Vec<String>,
+}
+
+impl Host {
+    /// Create an empty stanza for `name`.
+    pub fn new<S: Into<String>>(name: S) -> Host {
+        let name = name.into();
+        Host {
+            patterns: vec![name.clone()],
+            name,
+            options: Options::new(),
+            file: PathBuf::new(),
+            comments: vec![],
+        }
+    }
+
+    /// First value for a directive. Keywords are case insensitive.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options
+            .get(&key.to_lowercase())
+            .and_then(|values| values.first())
+            .map(|v| v.as_ref())
+    }
+
+    /// Every value for a directive, for the ones that can repeat.
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.options
+            .get(&key.to_lowercase())
+            .map(|values| values.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Add a directive, following ssh's first-value-wins rules.
+    pub fn insert(&mut self, key: &str, value: &str) {
+        insert_option(&mut self.options, key, value);
+    }
+
+    /// The real hostname. Falls back to the alias, like ssh does.
+    pub fn hostname(&self) -> &str {
+        self.get("hostname").unwrap_or(&self.name)
+    }
+
+    /// The User directive, if any.
+    pub fn user(&self) -> Option<&str> {
+        self.get("user")
+    }
+
+    /// The Port directive, if any.
+    pub fn port(&self) -> Option<&str> {
+        self.get("port")
+    }
+}
+
+/// A line in an ssh config we couldn't make sense of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Empty when parsing a string.
+    pub file: PathBuf,
+    /// Starts at 1.
+    pub line: usize,
+    /// Starts at 1, and counts characters rather than bytes.
+    pub column: usize,
+    /// The offending line.
+    pub text: String,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.file.as_os_str().is_empty() {
+            write!(f, "line {}:{}", self.line, self.column)?;
+        } else {
+            write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)?;
+        }
+        write!(f, ": {}: {}", self.message, self.text.trim())
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(err: ParseError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// What a stanza applies to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Condition {
+    /// Directives above the first `Host` or `Match` line apply to
+    /// every host.
+    #[default]
+    Always,
+    /// `Host pattern...`
+    Host(Vec<String>),
+    /// `Match criteria...`, which all have to hold.
+    Match(Vec<Criterion>),
+}
+
+impl fmt::Display for Condition {
+    /// The stanza's first line, more or less as written.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Always => write!(f, "(global)"),
+            Condition::Host(patterns) => write!(f, "Host {}", patterns.join(" ")),
+            Condition::Match(criteria) => {
+                write!(f, "Match")?;
+                for criterion in criteria {
+                    write!(f, " {}", criterion)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// One `[!]keyword [argument]` criterion on a `Match` line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Criterion {
+    pub negated: bool,
+    /// Lowercased: `all`, `canonical`, `final`, `exec`, `host`,
+    /// `originalhost`, `user`, `localuser`, or something newer that
+    /// we can't check and so never matches.
+    pub keyword: String,
+    pub arg: Option<String>,
+}
+
+impl fmt::Display for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "!")?;
+        }
+        write!(f, "{}", self.keyword)?;
+        if let Some(arg) = &self.arg {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Match keywords that don't take an argument.
+const MATCH_FLAGS: &[&str] = &["all", "canonical", "final"];
+
+impl Criterion {
+    /// Parse the arguments of a `Match` line.
+    pub fn parse_all(args: &[String]) -> Result<Vec<Criterion>, String> {
+        let mut criteria = vec![];
+        let mut args = args.iter();
+
+        while let Some(word) = args.next() {
+            let (negated, keyword) = match word.strip_prefix('!') {
+                Some(keyword) => (true, keyword.to_lowercase()),
+                None => (false, word.to_lowercase()),
+            };
+            let arg = if MATCH_FLAGS.contains(&keyword.as_ref()) {
+                None
+            } else if let Some(arg) = args.next() {
+                Some(arg.clone())
+            } else {
+                return Err(format!("Match {} needs an argument", keyword));
+            };
+            criteria.push(Criterion {
+                negated,
+                keyword,
+                arg,
+            });
+        }
+
+        // like ssh, `all` can only follow `canonical` or `final`
+        let all = criteria.iter().position(|c| c.keyword == "all");
+        if let Some(i) = all {
+            if criteria.len() != i + 1
+                || criteria[..i]
+                    .iter()
+                    .any(|c| c.keyword != "canonical" && c.keyword != "final")
+            {
+                return Err("Match all must appear alone".into());
+            }
+        }
+
+        Ok(criteria)
+    }
+}
+
+/// One `Host` or `Match` block as written, and its directives.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stanza {
+    pub condition: Condition,
+    pub options: Options,
+    /// The config file the stanza came from.
+    pub file: PathBuf,
+}
+
+/// A value ssh will use for a host, and the stanza it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting<'a> {
+    /// Lowercased, like the keys in `Options`.
+    pub key: String,
+    pub value: String,
+    pub stanza: &'a Stanza,
+}
+
+/// Everything a `Match` line might look at while we resolve a host.
+struct MatchState<'a> {
+    alias: &'a str,
+    host: &'a Host,
+    final_pass: bool,
+    /// Run `Match exec` commands? Otherwise they never match.
+    exec: bool,
+}
+
+impl Stanza {
+    /// The aliases on a `Host` line that get their own list entry.
+    pub fn aliases(&self) -> impl Iterator<Item = &String> {
+        let patterns = match &self.condition {
+            Condition::Host(patterns) => patterns.as_slice(),
+            _ => &[],
+        };
+        patterns.iter().filter(|p| is_alias(p))
+    }
+
+    /// Does a `Host` stanza apply to `alias`? Any negated pattern that
+    /// matches rules the stanza out, otherwise one plain pattern has to
+    /// match. Like ssh, the alias is lowercased but patterns aren't.
+    /// `Match` stanzas depend on more than the alias, so they're only
+    /// checked during `SshConfig::resolve`.
+    pub fn matches(&self, alias: &str) -> bool {
+        match &self.condition {
+            Condition::Always => true,
+            Condition::Host(patterns) => host_patterns_match(patterns, &alias.to_lowercase()),
+            Condition::Match(_) => false,
+        }
+    }
+
+    /// Does this stanza need ssh's second, "final" pass?
+    fn wants_final_pass(&self) -> bool {
+        match &self.condition {
+            Condition::Match(criteria) => criteria
+                .iter()
+                .any(|c| c.keyword == "canonical" || c.keyword == "final"),
+            _ => false,
+        }
+    }
+
+    /// Does this stanza apply, given what we've resolved so far?
+    fn applies(&self, state: &MatchState) -> bool {
+        let criteria = match &self.condition {
+            Condition::Match(criteria) => criteria,
+            _ => return self.matches(state.alias),
+        };
+
+        criteria.iter().all(|criterion| {
+            let arg = criterion.arg.as_deref().unwrap_or("");
+            let hit = match criterion.keyword.as_ref() {
+                "all" => true,
+                "canonical" | "final" => state.final_pass,
+                // the HostName we've got so far, or the alias
+                "host" => {
+                    pattern_list_matches(&arg.to_lowercase(), &state.host.hostname().to_lowercase())
+                }
+                "originalhost" => {
+                    pattern_list_matches(&arg.to_lowercase(), &state.alias.to_lowercase())
+                }
+                "user" => match state.host.user() {
+                    Some(user) => pattern_list_matches(arg, user),
+                    None => pattern_list_matches(arg, &local_user()),
+                },
+                "localuser" => pattern_list_matches(arg, &local_user()),
+                "exec" => state.exec && run_match_exec(arg, state),
+                _ => false,
+            };
+            hit != criterion.negated
+        })
+    }
+}
+
+/// Host-line matching: a negated pattern that matches wins, otherwise
+/// any plain pattern can match.
+fn host_patterns_match(patterns: &[String], alias: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if pattern_matches(negated, alias) {
+                return false;
+            }
+        } else if pattern_matches(pattern, alias) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// `Match` arguments are comma-separated pattern lists, with the same
+/// negation rules as `Host` lines.
+fn pattern_list_matches(list: &str, text: &str) -> bool {
+    host_patterns_match(
+        &list.split(',').map(|p| p.to_string()).collect::<Vec<_>>(),
+        text,
+    )
+}
+
+/// The local user name.
+pub fn local_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_default()
+}
+
+/// Run a `Match exec` command through the shell. It matches if it
+/// exits successfully.
+fn run_match_exec(command: &str, state: &MatchState) -> bool {
+    let user = local_user();
+    let command = expand_tokens(
+        command,
+        &[
+            ('h', state.host.hostname()),
+            ('n', state.alias),
+            ('p', state.host.port().unwrap_or("22")),
+            ('r', state.host.user().unwrap_or(&user)),
+            ('u', &user),
+        ],
+    );
+
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Expand ssh's `%x` tokens. `%%` is a literal `%`; unknown tokens are
+/// left alone.
+fn expand_tokens(text: &str, tokens: &[(char, &str)]) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some(t) => match tokens.iter().find(|(k, _)| *k == t) {
+                Some((_, value)) => out.push_str(value),
+                None => {
+                    out.push('%');
+                    out.push(t);
+                }
+            },
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Everything in an ssh config: the hosts you can connect to, plus
+/// every stanza so we can work out what applies to each of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SshConfig {
+    pub hosts: HostMap,
+    pub stanzas: Vec<Stanza>,
+    /// Lines skipped by `load_ssh_config_lenient`.
+    pub warnings: Vec<ParseError>,
+    /// Run `Match exec` commands to see if their stanzas apply? ssh
+    /// does, but they can be slow, so unless this is set they never
+    /// match.
+    pub match_exec: bool,
+}
+
+impl SshConfig {
+    /// Stanzas that apply to `alias`, in the order ssh reads them.
+    pub fn matching(&self, alias: &str) -> Vec<&Stanza> {
+        self.apply(alias).1
+    }
+
+    /// Every value `resolve` finds for `alias`, in the order ssh picks
+    /// them up, with the stanza each one came from.
+    pub fn explain(&self, alias: &str) -> Vec<Setting<'_>> {
+        self.apply(alias).2
+    }
+
+    /// The settings ssh will really use for `alias`: every matching
+    /// `Host` and `Match` stanza, wildcards included, with the first
+    /// value obtained for each keyword winning. `Match exec` commands
+    /// only run with `match_exec` set.
+    pub fn resolve(&self, alias: &str) -> Host {
+        self.apply(alias).0
+    }
+
+    /// Walk the stanzas like ssh does, returning the resolved host, the
+    /// stanzas that apply to it, and where each of its values came from.
+    fn apply(&self, alias: &str) -> (Host, Vec<&Stanza>, Vec<Setting<'_>>) {
+        let mut host = self
+            .hosts
+            .get(alias)
+            .cloned()
+            .unwrap_or_else(|| Host::new(alias));
+        host.options.clear();
+        let mut applied: Vec<&Stanza> = vec![];
+        let mut settings = vec![];
+
+        // `Match canonical` and `Match final` make ssh read the whole
+        // config a second time, keeping what it already found.
+        let passes: &[bool] = if self.stanzas.iter().any(|s| s.wants_final_pass()) {
+            &[false, true]
+        } else {
+            &[false]
+        };
+
+        for &final_pass in passes {
+            for stanza in &self.stanzas {
+                let state = MatchState {
+                    alias,
+                    host: &host,
+                    final_pass,
+                    exec: self.match_exec,
+                };
+                if !stanza.applies(&state) {
+                    continue;
+                }
+                for (key, values) in &stanza.options {
+                    for value in values {
+                        let before = host.get_all(key).len();
+                        host.insert(key, value);
+                        if host.get_all(key).len() > before {
+                            settings.push(Setting {
+                                key: key.clone(),
+                                value: value.clone(),
+                                stanza,
+                            });
+                        }
+                    }
+                }
+                if !applied.iter().any(|s| std::ptr::eq(*s, stanza)) {
+                    applied.push(stanza);
+                }
+            }
+        }
+        (host, applied, settings)
+    }
+}
+
+/// Add `value` to `options` unless `key` is single-valued and already
+/// set. Like ssh, repeating a value for a multi-valued key is a no-op.
+pub fn insert_option(options: &mut Options, key: &str, value: &str) {
+    let key = key.to_lowercase();
+    let multi = MULTI_VALUED.contains(&key.as_ref());
+    let values = options.entry(key).or_default();
+    if values.is_empty() || (multi && !values.iter().any(|v| v == value)) {
+        values.push(value.to_string());
+    }
+}
+
+/// Load every host and stanza, following `Include`s. The first line
+/// we can't parse is an error, with a `ParseError` inside.
+pub fn load_ssh_config(path: &str) -> io::Result<SshConfig> {
+    let mut parser = Parser::new(ssh_dir(), false);
+    parser.parse_file(&expand_tilde(path))?;
+    Ok(parser.config)
+}
+
+/// Like `load_ssh_config`, but lines we can't parse are skipped and
+/// collected in `SshConfig::warnings` instead.
+pub fn load_ssh_config_lenient(path: &str) -> io::Result<SshConfig> {
+    let mut parser = Parser::new(ssh_dir(), true);
+    parser.parse_file(&expand_tilde(path))?;
+    Ok(parser.config)
+}
+
+/// Like `load_ssh_config_lenient`, for several files read one after
+/// the other as if the first `Include`d the rest. Missing files are
+/// skipped, but one of them has to be there.
+pub fn load_ssh_configs_lenient(paths: &[String]) -> io::Result<SshConfig> {
+    let mut parser = Parser::new(ssh_dir(), true);
+    let mut found = false;
+    for path in paths {
+        parser.comments.clear();
+        match parser.parse_file(&expand_tilde(path)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            result => {
+                result?;
+                found = true;
+            }
+        }
+    }
+    if !found {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found", paths.join(", ")),
+        ));
+    }
+    Ok(parser.config)
+}
+
+/// Parse .ssh/config to a (sorted) map. Relative `Include` paths are
+/// looked up in ~/.ssh, like they are for the user's own config.
+pub fn parse_ssh_config<S: AsRef<str>>(config: S) -> Result<SshConfig, ParseError> {
+    let mut parser = Parser::new(ssh_dir(), false);
+    parser.parse(config.as_ref(), Path::new(""), &mut None)?;
+    Ok(parser.config)
+}
+
+/// ~/.ssh, where ssh looks for relative `Include` paths.
+fn ssh_dir() -> PathBuf {
+    expand_tilde("~/.ssh")
+}
+
+/// Replace a leading ~ with $HOME.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    if path == "~" || path.starts_with("~/") {
+        let home = env::var("HOME").expect("$HOME must be set");
+        PathBuf::from(path.replacen('~', &home, 1))
+    } else {
+        PathBuf::from(path)
+    }
+}
 
-/// For now just load the hostnames and their labels.
-pub fn load_ssh_config(path: &str) -> io::Result<HostMap> {
-    parse_ssh_config(&fs::read_to_string(
-        path.replace('~', &env::var("HOME").expect("$HOME must be set")),
-    )?)
+/// Is a Host pattern a plain alias you can ssh to? Wildcards and
+/// negations aren't. Neither are commas: ssh separates Host patterns
+/// with whitespace, so `a,b` is one pattern no real host can match.
+pub fn is_alias(pattern: &str) -> bool {
+    !pattern.is_empty() && !pattern.contains(&['*', '?', '!', ','][..])
 }
 
-/// Parse .ssh/config to a (sorted) map.
-pub fn parse_ssh_config<S: AsRef<str>>(config: S) -> io::Result<HostMap> {
-    let config = config.as_ref();
-    let mut map = HostMap::new();
+/// Does `text` match a pattern with `*` and `?` wildcards? This is
+/// the same (small) syntax ssh uses for Host patterns.
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // where to resume after the last `*`: (pattern idx, text idx)
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    p = sp + 1;
+                    t = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
 
-    let mut token = String::new(); // the token we're parsing
-    let mut line = vec![]; // current line
-    let mut skip_line = false; // skip until EOL for comments
-    let mut stanza = String::new(); // ssh config is broken into stanzas
-    let mut key = true; // parsing the key or the value?
+/// Expand a path with wildcards, glob(3)-style: results are sorted
+/// and wildcards don't match a leading `.`.
+fn glob(pattern: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![PathBuf::new()];
 
-    for c in config.chars() {
-        if skip_line {
-            if c == '\n' {
-                skip_line = false;
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !part.contains('*') && !part.contains('?') {
+            for path in paths.iter_mut() {
+                path.push(component);
             }
             continue;
         }
 
-        if c == '#' {
-            // skip comments
-            skip_line = true;
-            if !token.is_empty() {
-                line.push(token);
-                token = String::new();
+        let mut matches = vec![];
+        for dir in &paths {
+            let entries = match fs::read_dir(if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            }) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') && !part.starts_with('.') {
+                    continue;
+                }
+                if pattern_matches(&part, &name) {
+                    matches.push(dir.join(name));
+                }
             }
-        } else if key && (c == ' ' || c == '=') {
-            // "key = value" OR "key value" separator
-            if !token.is_empty() {
-                line.push(token);
-                token = String::new();
-                key = false;
+        }
+        matches.sort();
+        paths = matches;
+    }
+
+    Ok(paths.into_iter().filter(|p| p.exists()).collect())
+}
+
+/// Walks a config file and the files it includes.
+struct Parser {
+    /// Where relative `Include` paths live.
+    include_dir: PathBuf,
+    /// Skip bad lines instead of giving up?
+    lenient: bool,
+    /// Files we're in the middle of reading, to catch `Include` loops.
+    stack: Vec<PathBuf>,
+    /// Comment lines since the last blank line or directive.
+    comments: Vec<String>,
+    config: SshConfig,
+}
+
+impl Parser {
+    fn new(include_dir: PathBuf, lenient: bool) -> Parser {
+        Parser {
+            include_dir,
+            lenient,
+            stack: vec![],
+            comments: vec![],
+            config: SshConfig::default(),
+        }
+    }
+
+    /// Read and parse the top-level config file.
+    fn parse_file(&mut self, path: &Path) -> io::Result<()> {
+        let config = fs::read_to_string(path)?;
+        self.stack.push(fs::canonicalize(path)?);
+        let result = self.parse(&config, path, &mut None);
+        self.stack.pop();
+        Ok(result?)
+    }
+
+    /// Pull in every file matching an `Include` argument. `stanza` is
+    /// the index of the stanza we're inside of, since included files
+    /// inherit it.
+    fn include(
+        &mut self,
+        at: &Location,
+        pattern: &str,
+        stanza: Option<usize>,
+    ) -> Result<(), ParseError> {
+        let offset = at.text.find(pattern).unwrap_or(0);
+        let mut path = expand_tilde(pattern);
+        if path.is_relative() {
+            path = self.include_dir.join(path);
+        }
+
+        for file in glob(&path).map_err(|e| at.error(offset, e.to_string()))? {
+            let canonical = fs::canonicalize(&file).map_err(|e| at.error(offset, e.to_string()))?;
+            if self.stack.contains(&canonical) {
+                return Err(at.error(
+                    offset,
+                    format!("Include cycle: {} includes itself", file.display()),
+                ));
             }
-        } else if c == '\n' {
-            if !token.is_empty() {
-                line.push(token);
-                token = String::new();
-                key = true;
+            let config = fs::read_to_string(&file)
+                .map_err(|e| at.error(offset, format!("can't read {}: {}", file.display(), e)))?;
+
+            // whatever Host the included file ends in doesn't leak out
+            let mut inner = stanza;
+            self.stack.push(canonical);
+            let result = self.parse(&config, &file, &mut inner);
+            self.stack.pop();
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Add a directive to the current stanza and its hosts.
+    fn insert(&mut self, stanza: &mut Option<usize>, file: &Path, key: &str, value: &str) {
+        // directives above the first Host apply to everything
+        let idx = *stanza.get_or_insert_with(|| {
+            self.config.stanzas.push(Stanza {
+                file: file.to_path_buf(),
+                ..Stanza::default()
+            });
+            self.config.stanzas.len() - 1
+        });
+
+        let current = &mut self.config.stanzas[idx];
+        insert_option(&mut current.options, key, value);
+        for alias in current.aliases() {
+            if let Some(host) = self.config.hosts.get_mut(alias) {
+                host.insert(key, value);
             }
+        }
+    }
 
-            if line.is_empty() {
+    /// Start a new stanza.
+    fn push_stanza(&mut self, stanza: &mut Option<usize>, condition: Condition, file: &Path) {
+        *stanza = Some(self.config.stanzas.len());
+        self.config.stanzas.push(Stanza {
+            condition,
+            options: Options::new(),
+            file: file.to_path_buf(),
+        });
+    }
+
+    /// Parse the contents of `file`. In lenient mode bad lines are
+    /// skipped and kept as warnings, otherwise the first one is
+    /// returned.
+    fn parse(
+        &mut self,
+        config: &str,
+        file: &Path,
+        stanza: &mut Option<usize>,
+    ) -> Result<(), ParseError> {
+        for (i, text) in config.lines().enumerate() {
+            let at = Location {
+                file,
+                line: i + 1,
+                text,
+            };
+            if let Some(comment) = text.trim().strip_prefix('#') {
+                let comment = comment.trim_start_matches('#').trim();
+                if !comment.is_empty() {
+                    self.comments.push(comment.to_string());
+                }
                 continue;
-            } else if line.len() != 2 {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("can't parse line: {:?}", line),
-                ));
-            } else {
-                match line[0].to_lowercase().as_ref() {
-                    "host" => {
-                        let parsed = &line[1];
-                        // skip any Host patterns
-                        if parsed.contains('*')
-                            || parsed.contains('!')
-                            || parsed.contains('?')
-                            || parsed.contains(',')
-                            || parsed.contains(' ')
-                        {
-                            stanza.clear();
-                        } else {
-                            stanza = parsed.clone();
-                            // by default we assume host patterns are
-                            // actual hostnames
-                            map.insert(stanza.clone(), stanza.clone());
-                        }
+            }
+            let result = self.parse_line(&at, stanza);
+            self.comments.clear();
+            if let Err(err) = result {
+                if !self.lenient {
+                    return Err(err);
+                }
+                // a broken Host or Match line gets an empty stanza, so
+                // its directives don't land on the stanza above it
+                let key = text
+                    .trim_start()
+                    .split(|c: char| c.is_whitespace() || c == '=')
+                    .next()
+                    .unwrap_or("")
+                    .to_lowercase();
+                if key == "host" || key == "match" {
+                    self.push_stanza(stanza, Condition::Host(vec![]), file);
+                }
+                self.config.warnings.push(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse one line.
+    fn parse_line(&mut self, at: &Location, stanza: &mut Option<usize>) -> Result<(), ParseError> {
+        let directive = match lex_line(at.text) {
+            Ok(Some(directive)) => directive,
+            Ok(None) => return Ok(()),
+            Err((offset, message)) => return Err(at.error(offset, message)),
+        };
+
+        match directive.key.to_lowercase().as_ref() {
+            "host" => {
+                let patterns = directive.args;
+                let comments = &self.comments;
+                // only real aliases get an entry, not patterns
+                for alias in patterns.iter().filter(|p| is_alias(p)) {
+                    // a repeated alias adds to the first stanza
+                    self.config.hosts.entry(alias.clone()).or_insert_with(|| {
+                        let mut host = Host::new(alias.clone());
+                        host.patterns = patterns.clone();
+                        host.file = at.file.to_path_buf();
+                        host.comments = comments.clone();
+                        host
+                    });
+                }
+                self.push_stanza(stanza, Condition::Host(patterns), at.file);
+            }
+            "match" => {
+                let criteria = Criterion::parse_all(&directive.args)
+                    .map_err(|message| at.error(directive.offset, message))?;
+                self.push_stanza(stanza, Condition::Match(criteria), at.file);
+            }
+            "include" => {
+                for pattern in &directive.args {
+                    self.include(at, pattern, *stanza)?;
+                }
+            }
+            _ => self.insert(stanza, at.file, &directive.key, &directive.value()),
+        }
+
+        Ok(())
+    }
+}
+
+/// A line being parsed, for error messages.
+struct Location<'a> {
+    file: &'a Path,
+    line: usize,
+    text: &'a str,
+}
+
+impl Location<'_> {
+    /// An error at byte `offset` of this line.
+    fn error<S: Into<String>>(&self, offset: usize, message: S) -> ParseError {
+        ParseError {
+            file: self.file.to_path_buf(),
+            line: self.line,
+            column: self.text[..offset.min(self.text.len())].chars().count() + 1,
+            text: self.text.trim_end().to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Keywords whose argument is the rest of the line, untouched, since
+/// it's a command for the shell.
+const RAW_VALUED: &[&str] = &[
+    "knownhostscommand",
+    "localcommand",
+    "proxycommand",
+    "remotecommand",
+];
+
+/// One `Keyword arguments...` line.
+#[derive(Debug, PartialEq)]
+struct Directive {
+    key: String,
+    /// Arguments with quotes and escapes processed.
+    args: Vec<String>,
+    /// Everything after the keyword and its separator.
+    raw: String,
+    /// Where `raw` starts in the line.
+    offset: usize,
+    /// Where the last argument ends in the line.
+    end: usize,
+}
+
+impl Directive {
+    /// The value we store: the raw text for commands, otherwise the
+    /// arguments joined by single spaces.
+    fn value(&self) -> String {
+        if RAW_VALUED.contains(&self.key.to_lowercase().as_ref()) {
+            self.raw.clone()
+        } else {
+            self.args.join(" ")
+        }
+    }
+
+    /// The byte range of the line that `value()` came from.
+    fn value_span(&self) -> Range<usize> {
+        if RAW_VALUED.contains(&self.key.to_lowercase().as_ref()) {
+            self.offset..self.offset + self.raw.len()
+        } else {
+            self.offset..self.end
+        }
+    }
+}
+
+/// Split a line into a keyword and its arguments, the way ssh does:
+/// the keyword ends at whitespace or a single `=`, arguments are
+/// separated by spaces or tabs, can be "double" or 'single' quoted,
+/// and a `#` at the start of an argument comments out the rest of
+/// the line. Returns None for blank lines and comments. Errors come
+/// with the byte offset of the problem in `line`.
+fn lex_line(line: &str) -> Result<Option<Directive>, (usize, String)> {
+    let is_space = |c: char| c == ' ' || c == '\t' || c == '\r' || c == '\n';
+    let start = line.len() - line.trim_start_matches(is_space).len();
+    let trimmed = line.trim_matches(is_space);
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(None);
+    }
+
+    let key_end = trimmed
+        .find(|c| is_space(c) || c == '=')
+        .unwrap_or(trimmed.len());
+    let key = &trimmed[..key_end];
+    let mut rest = trimmed[key_end..].trim_start_matches(is_space);
+    if let Some(after_eq) = rest.strip_prefix('=') {
+        rest = after_eq.trim_start_matches(is_space);
+    }
+    let rest_offset = start + trimmed.len() - rest.len();
+
+    let mut args = vec![];
+    let mut end = 0;
+    let mut chars = rest.char_indices().peekable();
+    loop {
+        while chars.peek().is_some_and(|(_, c)| is_space(*c)) {
+            chars.next();
+        }
+        match chars.peek() {
+            None | Some((_, '#')) => break,
+            _ => {}
+        }
+
+        let mut arg = String::new();
+        let mut quote = None;
+        end = rest.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.peek() {
+                    Some(&(_, next)) if next == '\\' || next == '"' || next == '\'' => {
+                        arg.push(next);
+                        chars.next();
                     }
-                    "hostname" => {
-                        if !stanza.is_empty() {
-                            map.insert(stanza.clone(), line[1].clone());
-                            stanza.clear();
-                        }
+                    Some((_, ' ')) if quote.is_none() => {
+                        arg.push(' ');
+                        chars.next();
                     }
-                    _ => {}
+                    // unrecognised escapes are kept as-is
+                    _ => arg.push(c),
+                },
+                ' ' | '\t' if quote.is_none() => {
+                    end = i;
+                    break;
                 }
-                line.clear();
+                '"' | '\'' if quote.is_none() => quote = Some((i, c)),
+                c if Some(c) == quote.map(|(_, q)| q) => quote = None,
+                c => arg.push(c),
             }
-        } else if (c == ' ' || c == '=') && token.is_empty() {
-            // skip = and whitespace at start of value, key = value format
-            continue;
-        } else {
-            // regular char
-            token.push(c);
         }
+        if let Some((i, _)) = quote {
+            return Err((rest_offset + i, "unterminated quote".into()));
+        }
+        args.push(arg);
+    }
+
+    if args.is_empty() {
+        return Err((start + key_end, format!("{} needs an argument", key)));
     }
 
-    Ok(map)
+    Ok(Some(Directive {
+        key: key.to_string(),
+        args,
+        raw: rest.to_string(),
+        offset: rest_offset,
+        end: rest_offset + end,
+    }))
 }
 
 #[cfg(test)]
@@ -106,8 +982,10 @@ mod tests {
 
     #[test]
     fn test_config() {
-        let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
-        assert_eq!(11, config.len());
+        let config = load_ssh_config("./tests/test_config")
+            .expect("failed to parse config")
+            .hosts;
+        assert_eq!(13, config.len());
 
         assert_eq!(
             config.keys().cloned().collect::<Vec<_>>(),
@@ -121,13 +999,456 @@ mod tests {
                 "devserver",
                 "ec2-some-long-name.amazon.probably.com",
                 "ec2-some-long-namer.amazon.probably.com",
+                "uk.gw.lan",
+                "uk.lan",
                 "torrentz-server",
                 "midi-files.com",
             ]
         );
-        assert_eq!("torrentz-r-us.com", config.get("torrentz-server").unwrap());
-        assert_eq!("docker3.mycloud.net", config.get("docker3").unwrap());
-        assert_eq!("192.168.1.100", config.get("nas01").unwrap());
-        assert_eq!("midi-files.com", config.get("midi-files.com").unwrap());
+        assert_eq!("torrentz-r-us.com", config["torrentz-server"].hostname());
+        assert_eq!("docker3.mycloud.net", config["docker3"].hostname());
+        assert_eq!("192.168.1.100", config["nas01"].hostname());
+        assert_eq!("midi-files.com", config["midi-files.com"].hostname());
+    }
+
+    #[test]
+    fn test_host_options() {
+        let config = load_ssh_config("./tests/test_config")
+            .expect("failed to parse config")
+            .hosts;
+
+        let nixcraft = &config["nixcraft"];
+        assert_eq!(Some("nixcraft"), nixcraft.user());
+        assert_eq!(Some("4242"), nixcraft.port());
+        assert_eq!(
+            Some("/nfs/shared/users/nixcraft/keys/server1/id_rsa"),
+            nixcraft.get("IdentityFile")
+        );
+        assert_eq!(
+            vec!["hostname", "user", "port", "identityfile"],
+            nixcraft.options.keys().collect::<Vec<_>>()
+        );
+
+        assert_eq!(Some("midi-kid"), config["midi-files.com"].user());
+        assert_eq!(None, config["docker1"].user());
+    }
+
+    #[test]
+    fn test_multi_alias() {
+        let config = load_ssh_config("./tests/test_config")
+            .expect("failed to parse config")
+            .hosts;
+        for alias in &["uk.gw.lan", "uk.lan"] {
+            let host = &config[*alias];
+            assert_eq!("192.168.0.251", host.hostname());
+            assert_eq!(Some("nixcraft"), host.user());
+            assert_eq!(vec!["uk.gw.lan", "uk.lan"], host.patterns);
+        }
+        assert!(!config.contains_key("devserver,otherserver"));
+
+        let config = parse_ssh_config(
+            "Host web1 web? !web3 *.internal web2
+    User deploy
+",
+        )
+        .unwrap()
+        .hosts;
+        assert_eq!(vec!["web1", "web2"], config.keys().collect::<Vec<_>>());
+        assert_eq!(Some("deploy"), config["web2"].user());
+    }
+
+    #[test]
+    fn test_multi_valued() {
+        let config = parse_ssh_config(
+            "Host box
+    User one
+    User two
+    IdentityFile ~/.ssh/a
+    IdentityFile ~/.ssh/b
+    LocalForward 8080 localhost:80
+",
+        )
+        .unwrap()
+        .hosts;
+        let host = &config["box"];
+        assert_eq!(Some("one"), host.user());
+        assert_eq!(vec!["~/.ssh/a", "~/.ssh/b"], host.get_all("identityfile"));
+        assert_eq!(vec!["8080 localhost:80"], host.get_all("LocalForward"));
+    }
+
+    /// Load a test config, with includes relative to tests/include.
+    fn load_with_includes(path: &str) -> io::Result<SshConfig> {
+        let mut parser = Parser::new(PathBuf::from("./tests/include"), false);
+        parser.parse_file(Path::new(path))?;
+        Ok(parser.config)
+    }
+
+    #[test]
+    fn test_include() {
+        let config = load_with_includes("./tests/include/config").unwrap().hosts;
+        assert_eq!(
+            vec!["web1", "db", "mail", "main", "from-main-options", "after"],
+            config.keys().collect::<Vec<_>>()
+        );
+
+        // included into the middle of a stanza
+        assert_eq!(Some("admin"), config["main"].user());
+        assert_eq!(Some("2200"), config["main"].port());
+        assert_eq!(None, config["after"].user());
+
+        assert_eq!(
+            Path::new("./tests/include/conf.d/10-web.conf"),
+            config["web1"].file
+        );
+        assert_eq!(
+            Path::new("./tests/include/conf.d/nested/db"),
+            config["db"].file
+        );
+        assert_eq!(Path::new("./tests/include/config"), config["after"].file);
+    }
+
+    #[test]
+    fn test_several_files() {
+        let paths = vec![
+            "./tests/missing_config".to_string(),
+            "./tests/test_config".to_string(),
+            "./tests/include/config".to_string(),
+        ];
+        let config = load_ssh_configs_lenient(&paths).expect("failed to parse configs");
+        let first = load_ssh_config_lenient("./tests/test_config").unwrap();
+        assert!(config.hosts.len() > first.hosts.len());
+        assert_eq!(first.hosts.keys().next(), config.hosts.keys().next());
+
+        let err = load_ssh_configs_lenient(&paths[..1]).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        assert_eq!("./tests/missing_config not found", err.to_string());
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let err = load_with_includes("./tests/include/cycle/a").unwrap_err();
+        assert!(err.to_string().contains("Include cycle"));
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern_matches("*", "anything"));
+        assert!(pattern_matches("docker*", "docker1"));
+        assert!(pattern_matches("*.conf", "10-web.conf"));
+        assert!(pattern_matches("web?", "web1"));
+        assert!(pattern_matches("*.*.lan", "uk.gw.lan"));
+        assert!(!pattern_matches("web?", "web10"));
+        assert!(!pattern_matches("*.conf", "notes.txt"));
+        assert!(!pattern_matches("docker", "docker1"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
+
+        // `Host docker*` and `Host *` fill in what docker1 doesn't set
+        let docker1 = config.resolve("docker1");
+        assert_eq!("docker1.mycloud.net", docker1.hostname());
+        assert_eq!(Some("nixcraft"), docker1.user());
+        assert_eq!(Some("22"), docker1.port());
+        assert_eq!(vec!["~/.ssh/docker.key"], docker1.get_all("IdentityFile"));
+
+        // the first value obtained wins, even over `Host *`
+        let nixcraft = config.resolve("nixcraft");
+        assert_eq!(Some("4242"), nixcraft.port());
+        let midi = config.resolve("midi-files.com");
+        assert_eq!(Some("midi-kid"), midi.user());
+        assert_eq!(Some("no"), midi.get("forwardagent"));
+
+        // aliases that aren't in the list still get the wildcards
+        let unknown = config.resolve("dockerfile");
+        assert_eq!("dockerfile", unknown.hostname());
+        assert_eq!(vec!["~/.ssh/docker.key"], unknown.get_all("identityfile"));
+    }
+
+    #[test]
+    fn test_explain() {
+        let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
+        let settings = config.explain("docker1");
+        let origin = |key: &str| {
+            settings
+                .iter()
+                .find(|s| s.key == key)
+                .map(|s| (s.value.as_ref(), s.stanza.condition.to_string()))
+        };
+        assert_eq!(
+            Some(("docker1.mycloud.net", "Host docker1".into())),
+            origin("hostname")
+        );
+        assert_eq!(
+            Some(("~/.ssh/docker.key", "Host docker*".into())),
+            origin("identityfile")
+        );
+        assert_eq!(Some(("22", "Host *".into())), origin("port"));
+
+        // values that lose to an earlier one aren't listed
+        let settings = config.explain("nixcraft");
+        assert_eq!(1, settings.iter().filter(|s| s.key == "port").count());
+
+        let config = parse_ssh_config("Match user ops !host db*\n    Port 2222\n").unwrap();
+        assert_eq!(
+            "Match user ops !host db*",
+            config.stanzas[0].condition.to_string()
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
+        assert_eq!(vec!["nixcraft"], config.hosts["nixcraft"].comments);
+        assert_eq!(vec!["another comment"], config.hosts["nas01"].comments);
+        assert!(config.hosts["docker1"].comments.is_empty());
+
+        // a blank line or a directive in between breaks the link
+        let config = parse_ssh_config("# old\n\nHost a\n  # about b\n  Port 1\nHost b\n").unwrap();
+        assert!(config.hosts["a"].comments.is_empty());
+        assert!(config.hosts["b"].comments.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_negation() {
+        let config = parse_ssh_config(
+            "IdentitiesOnly yes
+Host web*
+    IdentityFile ~/.ssh/web
+Host *.prod !bastion.prod
+    User deploy
+    IdentityFile ~/.ssh/prod
+Host *
+    User me
+",
+        )
+        .unwrap();
+
+        let host = config.resolve("web1.prod");
+        assert_eq!(Some("yes"), host.get("identitiesonly"));
+        assert_eq!(Some("deploy"), host.user());
+        assert_eq!(
+            vec!["~/.ssh/web", "~/.ssh/prod"],
+            host.get_all("identityfile")
+        );
+
+        let bastion = config.resolve("bastion.prod");
+        assert_eq!(Some("me"), bastion.user());
+        assert!(bastion.get_all("identityfile").is_empty());
+
+        // ssh lowercases the host, but not the patterns
+        assert_eq!(Some("deploy"), config.resolve("DB.prod").user());
+    }
+
+    /// Lex a line into (keyword, args), panicking on blank lines.
+    fn lex(line: &str) -> (String, Vec<String>) {
+        let directive = lex_line(line).unwrap().expect("expected a directive");
+        (directive.key, directive.args)
+    }
+
+    #[test]
+    fn test_lex_separators() {
+        let expected = ("HostName".to_string(), vec!["example.com".to_string()]);
+        assert_eq!(expected, lex("HostName example.com"));
+        assert_eq!(expected, lex("HostName=example.com"));
+        assert_eq!(expected, lex("HostName = example.com"));
+        assert_eq!(expected, lex("  HostName\texample.com"));
+        assert_eq!(expected, lex("\tHostName\t=\texample.com\t"));
+        assert_eq!(expected, lex("HostName example.com\r"));
+    }
+
+    #[test]
+    fn test_lex_quotes() {
+        assert_eq!(
+            vec!["~/My Keys/id_rsa"],
+            lex("IdentityFile \"~/My Keys/id_rsa\"").1
+        );
+        assert_eq!(vec!["it's here"], lex("IdentityFile 'it\\'s here'").1);
+        assert_eq!(vec!["a b", "c"], lex("Host \"a b\" c").1);
+        assert_eq!(vec!["a b"], lex("Host a\\ b").1);
+        assert_eq!(vec!["mid quoted"], lex("Host mid\" quoted\"").1);
+        assert!(lex_line("Host \"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_lex_comments() {
+        assert!(lex_line("").unwrap().is_none());
+        assert!(lex_line("   \t").unwrap().is_none());
+        assert!(lex_line("# a comment").unwrap().is_none());
+        assert!(lex_line("\t# an indented comment").unwrap().is_none());
+        assert_eq!(vec!["a", "b"], lex("Host a b # trailing comment").1);
+        assert_eq!(vec!["a#b"], lex("Host a#b").1);
+        assert_eq!(vec!["#not-a-comment"], lex("Host \"#not-a-comment\"").1);
+        assert!(lex_line("Host").is_err());
+        assert!(lex_line("Host # nothing").is_err());
+    }
+
+    #[test]
+    fn test_lex_raw_commands() {
+        let directive = lex_line("ProxyCommand  ssh gw nc %h %p 2> /dev/null")
+            .unwrap()
+            .unwrap();
+        assert_eq!("ssh gw nc %h %p 2> /dev/null", directive.value());
+        let directive = lex_line("LocalForward 8080   localhost:80")
+            .unwrap()
+            .unwrap();
+        assert_eq!("8080 localhost:80", directive.value());
+    }
+
+    #[test]
+    fn test_parse_line_endings() {
+        let config = parse_ssh_config(
+            "Host one\r\n\tHostName one.example.com\r\nHost two\r\n  User=root\r\n  HostName two.example.com",
+        )
+        .unwrap()
+        .hosts;
+        assert_eq!("one.example.com", config["one"].hostname());
+        assert_eq!(Some("root"), config["two"].user());
+        // no trailing newline, but still there
+        assert_eq!("two.example.com", config["two"].hostname());
+
+        let config = parse_ssh_config("Host \"quoted alias\" tabbed\n\tUser\tme\n")
+            .unwrap()
+            .hosts;
+        assert_eq!(
+            vec!["quoted alias", "tabbed"],
+            config.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(Some("me"), config["quoted alias"].user());
+    }
+
+    #[test]
+    fn test_match() {
+        let config = parse_ssh_config(
+            "Host web1
+    HostName web1.example.com
+Host db
+    HostName db.internal
+    User dba
+Match host *.example.com
+    User deploy
+Match originalhost web1,db !user ops
+    Port 2222
+Host admin
+    User ops
+Match user ops
+    Port 2200
+Match all
+    ForwardAgent no
+",
+        )
+        .unwrap();
+
+        // `Match host` checks the HostName, not the alias
+        let web1 = config.resolve("web1");
+        assert_eq!(Some("deploy"), web1.user());
+        assert_eq!(Some("2222"), web1.port());
+        assert_eq!(Some("no"), web1.get("forwardagent"));
+
+        let db = config.resolve("db");
+        assert_eq!(Some("2222"), db.port());
+        assert_eq!(Some("dba"), db.user());
+
+        let admin = config.resolve("admin");
+        assert_eq!(Some("2200"), admin.port());
+
+        // Match blocks don't get stuck onto the Host above them
+        assert_eq!(None, config.hosts["web1"].user());
+        assert_eq!(None, config.hosts["db"].get("forwardagent"));
+        assert_eq!(
+            vec!["web1", "db", "admin"],
+            config.hosts.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_match_final() {
+        let config = parse_ssh_config(
+            "Match final
+    Port 2222
+Match !final
+    User first
+Match canonical all
+    ForwardAgent yes
+",
+        )
+        .unwrap();
+        let host = config.resolve("box");
+        assert_eq!(Some("2222"), host.port());
+        assert_eq!(Some("first"), host.user());
+        assert_eq!(Some("yes"), host.get("forwardagent"));
+    }
+
+    #[test]
+    fn test_match_exec() {
+        let mut config = parse_ssh_config(
+            "Match exec \"test %n = box\"
+    User exec-user
+",
+        )
+        .unwrap();
+        assert_eq!(None, config.resolve("box").user());
+        config.match_exec = true;
+        assert_eq!(Some("exec-user"), config.resolve("box").user());
+        assert_eq!(None, config.resolve("other").user());
+    }
+
+    #[test]
+    fn test_match_errors() {
+        assert!(parse_ssh_config("Match host\n").is_err());
+        assert!(parse_ssh_config("Match all host x\n").is_err());
+        assert!(parse_ssh_config("Match final all\n").is_ok());
+    }
+
+    #[test]
+    fn test_expand_tokens() {
+        let tokens = [('h', "example.com"), ('p', "22")];
+        assert_eq!("example.com:22", expand_tokens("%h:%p", &tokens));
+        assert_eq!("100% %z", expand_tokens("100%% %z", &tokens));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = parse_ssh_config("Host ok\n    User me\nHost \"broken\n").unwrap_err();
+        assert_eq!(3, err.line);
+        assert_eq!(6, err.column);
+        assert_eq!("Host \"broken", err.text);
+        assert_eq!(
+            "line 3:6: unterminated quote: Host \"broken",
+            err.to_string()
+        );
+
+        let err = load_ssh_config("./tests/broken_config").unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        let err = err.get_ref().unwrap().downcast_ref::<ParseError>().unwrap();
+        assert_eq!(Path::new("./tests/broken_config"), err.file);
+        assert_eq!(
+            "./tests/broken_config:3:6: unterminated quote: Host \"unterminated",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_lenient() {
+        let config = load_ssh_config_lenient("./tests/broken_config").unwrap();
+        assert_eq!(
+            vec!["good", "after"],
+            config.hosts.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(3, 6), (6, 9), (8, 7)],
+            config
+                .warnings
+                .iter()
+                .map(|w| (w.line, w.column))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("Port needs an argument", config.warnings[1].message);
+
+        // directives under broken Host and Match lines go nowhere
+        assert_eq!(None, config.resolve("good").user());
+        assert_eq!(None, config.resolve("after").user());
+        assert_eq!("after.example.com", config.resolve("after").hostname());
     }
 }
@@ -0,0 +1,537 @@
+//! A lossless model of a single ssh config file, for editing it in
+//! place without disturbing anything we didn't touch.
+
+use {
+    super::{lex_line, Directive},
+    crate::files::write_atomic,
+    std::{
+        fmt, fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// An ssh config file, kept line by line exactly as written: comments,
+/// blank lines, indentation, keyword casing, `=` vs space and line
+/// endings all survive a round trip.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    /// Each line with its line ending, if it has one.
+    lines: Vec<String>,
+}
+
+/// Where a `Host` or `Match` block sits in the document.
+#[derive(Debug)]
+struct Block {
+    /// The `Host` or `Match` line.
+    header: usize,
+    /// One past the block's last directive. Comments and blank lines
+    /// after that are left for whatever comes next. Lines we can't make
+    /// sense of count as directives, so they stay with their block.
+    end: usize,
+    /// The patterns of a `Host` block. Empty for `Match`.
+    patterns: Vec<String>,
+}
+
+impl Document {
+    /// Split a config into lines, keeping their line endings.
+    pub fn parse(text: &str) -> Document {
+        Document {
+            lines: text.split_inclusive('\n').map(String::from).collect(),
+        }
+    }
+
+    /// Read a config file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Document> {
+        Ok(Document::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Write the document over `path`. The old file is copied to
+    /// `backup_path(path)` first, and the new one is written to a temp
+    /// file that's renamed into place, so a crash can't leave a half
+    /// written config behind. Symlinks are followed.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = fs::canonicalize(path.as_ref()).unwrap_or_else(|_| path.as_ref().into());
+        if path.exists() {
+            fs::copy(&path, backup_path(&path))?;
+        }
+        write_atomic(&path, self.to_string().as_bytes())
+    }
+
+    /// Aliases from every `Host` line, patterns included, in order.
+    pub fn hosts(&self) -> Vec<String> {
+        self.blocks()
+            .into_iter()
+            .flat_map(|block| block.patterns)
+            .collect()
+    }
+
+    /// Every directive in the stanza for `alias`, as (key, value).
+    pub fn directives(&self, alias: &str) -> io::Result<Vec<(String, String)>> {
+        let block = self.find(alias)?;
+        Ok((block.header + 1..block.end)
+            .filter_map(|i| self.directive(i))
+            .map(|d| (d.key.clone(), d.value()))
+            .collect())
+    }
+
+    /// The first value of `key` in the stanza for `alias`.
+    pub fn get(&self, alias: &str, key: &str) -> io::Result<Option<String>> {
+        let block = self.find(alias)?;
+        Ok(self
+            .find_key(&block, key)
+            .and_then(|i| self.directive(i))
+            .map(|d| d.value()))
+    }
+
+    /// Set `key` in the stanza for `alias`. An existing line keeps its
+    /// indentation, spelling, separator and trailing comment; otherwise
+    /// a new line goes after the stanza's last directive. `value` is
+    /// written as-is, so quote it if it needs quoting. Stanzas shared by
+    /// several aliases change for all of them.
+    pub fn set(&mut self, alias: &str, key: &str, value: &str) -> io::Result<()> {
+        let block = self.find(alias)?;
+        if let Some(i) = self.find_key(&block, key) {
+            self.replace_value(i, value);
+        } else {
+            let line = format!("{}{} {}", self.indent(&block), key, value);
+            self.insert_lines(block.end, vec![line]);
+        }
+        Ok(())
+    }
+
+    /// Remove the first `key` line from the stanza for `alias`, the one
+    /// `get` returns and `set` changes. Later ones, like a second
+    /// IdentityFile, stay. Returns whether there was one.
+    pub fn remove(&mut self, alias: &str, key: &str) -> io::Result<bool> {
+        let block = self.find(alias)?;
+        match self.find_key(&block, key) {
+            Some(i) => {
+                self.lines.remove(i);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Add a new `Host` stanza. It goes above a trailing `Host *` (and
+    /// the comments right above it), since ssh uses the first value it
+    /// finds and `Host *` would otherwise win.
+    pub fn add_host(&mut self, alias: &str, options: &[(String, String)]) -> io::Result<()> {
+        if self.find(alias).is_ok() {
+            return Err(io::Error::other(format!("Host {} already exists", alias)));
+        }
+
+        let indent = self
+            .blocks()
+            .iter()
+            .find(|b| b.end > b.header + 1)
+            .map(|b| self.indent(b))
+            .unwrap_or_else(|| "    ".into());
+        let mut lines = vec![format!("Host {}", quote(alias))];
+        for (key, value) in options {
+            lines.push(format!("{}{} {}", indent, key, value));
+        }
+
+        let wildcard = self
+            .blocks()
+            .into_iter()
+            .find(|b| b.patterns.len() == 1 && b.patterns[0] == "*");
+        if let Some(wildcard) = wildcard {
+            let at = self.comments_above(wildcard.header);
+            lines.push(String::new());
+            self.insert_lines(at, lines);
+        } else {
+            if self.lines.last().is_some_and(|l| !self.is_blank_text(l)) {
+                lines.insert(0, String::new());
+            }
+            let end = self.lines.len();
+            self.insert_lines(end, lines);
+        }
+        Ok(())
+    }
+
+    /// Remove `alias`. If its `Host` line has other patterns only the
+    /// alias goes, otherwise the whole stanza does, along with the
+    /// comments right above it.
+    pub fn remove_host(&mut self, alias: &str) -> io::Result<()> {
+        let block = self.find(alias)?;
+        if block.patterns.len() > 1 {
+            let patterns = block
+                .patterns
+                .iter()
+                .filter(|p| *p != alias)
+                .cloned()
+                .collect::<Vec<_>>();
+            self.set_patterns(block.header, &patterns);
+            return Ok(());
+        }
+
+        let at = self.comments_above(block.header);
+        self.lines.drain(at..block.end);
+        // don't leave a double blank line where the stanza was
+        if at < self.lines.len() && self.is_blank(at) && (at == 0 || self.is_blank(at - 1)) {
+            self.lines.remove(at);
+        }
+        Ok(())
+    }
+
+    /// Rename `alias` on its `Host` line, leaving the rest of the line
+    /// alone.
+    pub fn rename_host(&mut self, alias: &str, new_alias: &str) -> io::Result<()> {
+        if alias != new_alias && self.find(new_alias).is_ok() {
+            return Err(io::Error::other(format!(
+                "Host {} already exists",
+                new_alias
+            )));
+        }
+        let block = self.find(alias)?;
+        let patterns = block
+            .patterns
+            .iter()
+            .map(|p| if p == alias { new_alias } else { p }.to_string())
+            .collect::<Vec<_>>();
+        self.set_patterns(block.header, &patterns);
+        Ok(())
+    }
+
+    /// A line without its line ending.
+    fn content(&self, i: usize) -> &str {
+        let line = &self.lines[i];
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+
+    /// The start of the run of comment lines right above line `at`, or
+    /// `at` if there aren't any.
+    fn comments_above(&self, mut at: usize) -> usize {
+        while at > 0 && self.content(at - 1).trim_start().starts_with('#') {
+            at -= 1;
+        }
+        at
+    }
+
+    /// The parsed directive on line `i`, if there is one.
+    fn directive(&self, i: usize) -> Option<Directive> {
+        lex_line(self.content(i)).ok().flatten()
+    }
+
+    /// Is line `i` a `key` directive?
+    fn is_key(&self, i: usize, key: &str) -> bool {
+        self.directive(i)
+            .is_some_and(|d| d.key.eq_ignore_ascii_case(key))
+    }
+
+    fn is_blank(&self, i: usize) -> bool {
+        self.is_blank_text(&self.lines[i])
+    }
+
+    fn is_blank_text(&self, line: &str) -> bool {
+        line.trim().is_empty()
+    }
+
+    /// The line ending to use for new lines: whatever the file uses.
+    fn newline(&self) -> &'static str {
+        if self.lines.first().is_some_and(|l| l.ends_with("\r\n")) {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// The indentation of a block's directives.
+    fn indent(&self, block: &Block) -> String {
+        (block.header + 1..block.end)
+            .find(|&i| self.directive(i).is_some())
+            .map(|i| {
+                let line = self.content(i);
+                line[..line.len() - line.trim_start().len()].to_string()
+            })
+            .unwrap_or_else(|| "    ".into())
+    }
+
+    /// Insert lines (without line endings) before line `at`.
+    fn insert_lines(&mut self, at: usize, lines: Vec<String>) {
+        let newline = self.newline();
+        // the line before might be the last one, with no line ending
+        if at > 0 && !self.lines[at - 1].ends_with('\n') {
+            self.lines[at - 1].push_str(newline);
+        }
+        for (n, line) in lines.into_iter().enumerate() {
+            self.lines.insert(at + n, format!("{}{}", line, newline));
+        }
+    }
+
+    /// Swap the value on line `i`, keeping everything around it.
+    fn replace_value(&mut self, i: usize, value: &str) {
+        if let Some(directive) = self.directive(i) {
+            let span = directive.value_span();
+            self.lines[i].replace_range(span, value);
+        }
+    }
+
+    /// Rewrite the patterns on a `Host` line.
+    fn set_patterns(&mut self, header: usize, patterns: &[String]) {
+        let value = patterns
+            .iter()
+            .map(|p| quote(p))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.replace_value(header, &value);
+    }
+
+    /// The first `key` line in a block.
+    fn find_key(&self, block: &Block, key: &str) -> Option<usize> {
+        (block.header + 1..block.end).find(|&i| self.is_key(i, key))
+    }
+
+    /// The `Host` block that lists `alias`.
+    fn find(&self, alias: &str) -> io::Result<Block> {
+        self.blocks()
+            .into_iter()
+            .find(|b| b.patterns.iter().any(|p| p == alias))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no Host stanza for {}", alias),
+                )
+            })
+    }
+
+    /// Every `Host` and `Match` block, in order.
+    fn blocks(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = vec![];
+        for i in 0..self.lines.len() {
+            let directive = match lex_line(self.content(i)) {
+                Ok(Some(directive)) => directive,
+                Ok(None) => continue,
+                // not ours to fix, but it belongs to the block it's in
+                Err(_) => {
+                    if let Some(block) = blocks.last_mut() {
+                        block.end = i + 1;
+                    }
+                    continue;
+                }
+            };
+            match directive.key.to_lowercase().as_ref() {
+                "host" => blocks.push(Block {
+                    header: i,
+                    end: i + 1,
+                    patterns: directive.args,
+                }),
+                "match" => blocks.push(Block {
+                    header: i,
+                    end: i + 1,
+                    patterns: vec![],
+                }),
+                _ => {
+                    if let Some(block) = blocks.last_mut() {
+                        block.end = i + 1;
+                    }
+                }
+            }
+        }
+        blocks
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            f.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where `Document::save` copies the old file: `config.bak` for
+/// `config`.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+/// Quote a single argument if ssh would otherwise split it up or read
+/// it as a comment.
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && !arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '#')
+    {
+        return arg.to_string();
+    }
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::files::ScratchDir};
+
+    const MESSY: &str = concat!(
+        "# top comment\r\n",
+        "Host one two\r\n",
+        "\tHostName=one.example.com # trailing\r\n",
+        "  user = root\r\n",
+        "\r\n",
+        "Host *\r\n",
+        "    Port 22",
+    );
+
+    #[test]
+    fn test_round_trip() {
+        let config = fs::read_to_string("./tests/test_config").unwrap();
+        assert_eq!(config, Document::parse(&config).to_string());
+        assert_eq!(MESSY, Document::parse(MESSY).to_string());
+        assert_eq!("", Document::parse("").to_string());
+    }
+
+    #[test]
+    fn test_set() {
+        let mut doc = Document::parse(MESSY);
+        doc.set("one", "HostName", "uno.example.com").unwrap();
+        doc.set("two", "User", "admin").unwrap();
+        doc.set("one", "Port", "2222").unwrap();
+        assert_eq!(
+            concat!(
+                "# top comment\r\n",
+                "Host one two\r\n",
+                "\tHostName=uno.example.com # trailing\r\n",
+                "  user = admin\r\n",
+                "\tPort 2222\r\n",
+                "\r\n",
+                "Host *\r\n",
+                "    Port 22",
+            ),
+            doc.to_string()
+        );
+        assert_eq!(Some("2222".into()), doc.get("two", "port").unwrap());
+        assert!(doc.set("missing", "User", "x").is_err());
+
+        // a new line after a last line with no line ending
+        doc.set("*", "User", "me").unwrap();
+        assert!(doc.to_string().ends_with("    Port 22\r\n    User me\r\n"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut doc = Document::parse(MESSY);
+        assert!(doc.remove("one", "user").unwrap());
+        assert!(!doc.remove("one", "user").unwrap());
+        assert_eq!(
+            concat!(
+                "# top comment\r\n",
+                "Host one two\r\n",
+                "\tHostName=one.example.com # trailing\r\n",
+                "\r\n",
+                "Host *\r\n",
+                "    Port 22",
+            ),
+            doc.to_string()
+        );
+    }
+
+    #[test]
+    fn test_add_host() {
+        let mut doc = Document::load("./tests/test_config").unwrap();
+        let options = vec![
+            ("HostName".to_string(), "new.example.com".to_string()),
+            ("IdentityFile".to_string(), quote("~/My Keys/id")),
+        ];
+        doc.add_host("new", &options).unwrap();
+        assert!(doc.add_host("new", &options).is_err());
+
+        let text = doc.to_string();
+        assert!(text.contains(
+            "Host midi-files.com
+    User midi-kid
+Host new
+    HostName new.example.com
+    IdentityFile \"~/My Keys/id\"
+
+Host *
+"
+        ));
+
+        let config = crate::ssh_config::parse_ssh_config(&text).unwrap();
+        let new = config.resolve("new");
+        assert_eq!("new.example.com", new.hostname());
+        assert_eq!(vec!["~/My Keys/id"], new.get_all("identityfile"));
+        assert_eq!(Some("nixcraft"), new.user());
+
+        // no Host * to go in front of
+        let mut doc = Document::parse("Host a\n  User a");
+        doc.add_host("b", &[("User".into(), "b".into())]).unwrap();
+        assert_eq!("Host a\n  User a\n\nHost b\n  User b\n", doc.to_string());
+    }
+
+    #[test]
+    fn test_remove_host() {
+        let mut doc = Document::parse("Host a\n  User a\n\nHost b\n  User b\n\nHost c\n");
+        doc.remove_host("b").unwrap();
+        assert_eq!("Host a\n  User a\n\nHost c\n", doc.to_string());
+        assert!(doc.remove_host("b").is_err());
+
+        let mut doc = Document::parse(MESSY);
+        doc.remove_host("one").unwrap();
+        assert!(doc
+            .to_string()
+            .starts_with("# top comment\r\nHost two\r\n\tHostName="));
+
+        // its comments go with it, but not the ones after the last host
+        let mut doc = Document::parse(
+            "Host a\n  User a\n# about a\n\n# shy: favorite\n#shy: favorite\nHost b\n  User b\n",
+        );
+        doc.remove_host("b").unwrap();
+        assert_eq!("Host a\n  User a\n# about a\n\n", doc.to_string());
+    }
+
+    #[test]
+    fn test_bad_lines() {
+        let text = "Host a\n  User a\n  ProxyCommand \"nc %h\nHost b\n  User b\n";
+        let mut doc = Document::parse(text);
+        doc.set("a", "Port", "22").unwrap();
+        assert_eq!(
+            "Host a\n  User a\n  ProxyCommand \"nc %h\n  Port 22\nHost b\n  User b\n",
+            doc.to_string()
+        );
+        doc.remove_host("a").unwrap();
+        assert_eq!("Host b\n  User b\n", doc.to_string());
+    }
+
+    #[test]
+    fn test_rename_host() {
+        let mut doc = Document::parse(MESSY);
+        doc.rename_host("two", "dos").unwrap();
+        assert_eq!(vec!["one", "dos", "*"], doc.hosts());
+        assert!(doc.rename_host("one", "dos").is_err());
+        assert_eq!(
+            vec![
+                ("HostName".to_string(), "one.example.com".to_string()),
+                ("user".to_string(), "root".to_string())
+            ],
+            doc.directives("dos").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = ScratchDir::new("document");
+        let path = dir.join("config");
+        fs::write(&path, MESSY).unwrap();
+
+        let mut doc = Document::load(&path).unwrap();
+        doc.set("one", "User", "admin").unwrap();
+        doc.save(&path).unwrap();
+
+        assert_eq!(MESSY, fs::read_to_string(backup_path(&path)).unwrap());
+        assert_eq!(doc.to_string(), fs::read_to_string(&path).unwrap());
+        assert!(!dir.join("config.shy-tmp").exists());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!("plain", quote("plain"));
+        assert_eq!("\"with space\"", quote("with space"));
+        assert_eq!("\"say \\\"hi\\\"\"", quote("say \"hi\""));
+        assert_eq!("\"\"", quote(""));
+    }
+}
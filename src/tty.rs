@@ -0,0 +1,98 @@
+//! The terminal, opened as `/dev/tty` so stdout stays free for
+//! `--print`. termion only knows how to put stdout in raw mode and ask
+//! it for the size, so those are done here.
+
+use {
+    std::{
+        fs::File,
+        io::{self, Write},
+        mem,
+        os::unix::io::AsRawFd,
+        sync::Mutex,
+    },
+    termion::{cursor::Show, get_tty, screen::ToMainScreen},
+};
+
+/// What the open `Tty` has to put back, for `reset` to find after a
+/// panic.
+static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// `/dev/tty` in raw mode, until `restore` is called.
+pub struct Tty {
+    file: File,
+    /// The settings to put back.
+    saved: libc::termios,
+}
+
+impl Tty {
+    /// Open the terminal and switch it to raw mode.
+    pub fn open() -> io::Result<Tty> {
+        let file = get_tty()?;
+        let fd = file.as_raw_fd();
+        let mut saved: libc::termios = unsafe { mem::zeroed() };
+        check(unsafe { libc::tcgetattr(fd, &mut saved) })?;
+
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) })?;
+        if let Ok(mut global) = SAVED.lock() {
+            *global = Some(saved);
+        }
+        Ok(Tty { file, saved })
+    }
+
+    /// Put the terminal back how we found it.
+    pub fn restore(&self) -> io::Result<()> {
+        if let Ok(mut global) = SAVED.lock() {
+            *global = None;
+        }
+        let fd = self.file.as_raw_fd();
+        check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, &self.saved) })
+    }
+
+    /// The terminal's size, in columns and rows.
+    pub fn size(&self) -> io::Result<(u16, u16)> {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        check(unsafe { libc::ioctl(self.file.as_raw_fd(), libc::TIOCGWINSZ, &mut size) })?;
+        Ok((size.ws_col, size.ws_row))
+    }
+
+    /// For writing through a shared reference.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+impl Write for Tty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Put the terminal back from a panic hook, where the `Tty` can't be
+/// reached: its settings, the cursor and the main screen. Does nothing
+/// if no `Tty` is open.
+pub fn reset() {
+    let saved = match SAVED.lock().ok().and_then(|mut global| global.take()) {
+        Some(saved) => saved,
+        None => return,
+    };
+    if let Ok(mut file) = get_tty() {
+        unsafe { libc::tcsetattr(file.as_raw_fd(), libc::TCSANOW, &saved) };
+        let _ = write!(file, "{}{}", Show, ToMainScreen);
+        let _ = file.flush();
+    }
+}
+
+/// A libc return value as a Result.
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
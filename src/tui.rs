@@ -1,36 +1,94 @@
 use {
     crate::{
-        color,
-        ssh_config::{load_ssh_config, HostMap},
+        color::{self, Style, Theme},
+        editor::LineEditor,
+        favorites::{is_annotated, Favorites},
+        form::{Form, FormAction, FormKind},
+        history::{self, History},
+        keymap::{Action, KeyMode, Keymap, Lookup},
+        launch::{default_actions, Actions, Launch},
+        menu::{Menu, MenuAction},
+        nav::Nav,
+        search::{entries, rank, Entry, Match, Query},
+        ssh_config::{
+            document::Document, load_ssh_configs_lenient, local_user, Host, ParseError, Setting,
+            SshConfig,
+        },
+        tty::Tty,
     },
     flume::{unbounded, Receiver, Selector},
-    fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher},
+    fuzzy_matcher::skim::SkimMatcherV2,
     std::{
-        borrow::Cow,
-        io::{self, Stdout, Write},
+        cmp::Reverse,
+        io::{self, BufWriter, Write},
         thread,
     },
     termion::{
         clear::{All as ClearAll, CurrentLine as ClearLine},
         cursor::{Goto, Hide as HideCursor, Show as ShowCursor},
         event::Key,
+        get_tty,
         input::TermRead,
-        raw::{IntoRawMode, RawTerminal},
         screen::{ToAlternateScreen, ToMainScreen},
-        terminal_size,
     },
+    unicode_width::UnicodeWidthStr,
 };
 
+/// The detail pane folds away on terminals narrower than this.
+const DETAIL_MIN_COLS: u16 = 80;
+
+/// Settings the detail pane lists first, in this order, with their
+/// usual spelling.
+const DETAIL_KEYS: &[&str] = &[
+    "User",
+    "HostName",
+    "Port",
+    "IdentityFile",
+    "ProxyJump",
+    "LocalForward",
+    "RemoteForward",
+    "DynamicForward",
+];
+
 /// App state.
 pub struct TUI {
     pub mode: Mode,
     status: SearchStatus,
-    input: String,
+    input: LineEditor,
+    /// Position in `visible`, not in the config.
     selected: usize,
     offset: usize,
+    /// Indexes of the hosts on screen, in the order they're shown.
+    visible: Vec<usize>,
     size: (u16, u16),
-    hosts: HostMap,
-    stdout: RawTerminal<Stdout>,
+    /// The ssh configs we read. New hosts go in the first one.
+    config_paths: Vec<String>,
+    config: SshConfig,
+    /// What search looks at for each host, in config order.
+    entries: Vec<Entry>,
+    query: Query,
+    form: Option<Form>,
+    menu: Option<Menu>,
+    /// Shown in the status bar until the next key press.
+    message: Option<String>,
+    /// Show the detail pane, if there's room?
+    pub detail: bool,
+    pub sort: Sort,
+    pub theme: Theme,
+    /// Past launches, for sorting by frecency.
+    pub history: History,
+    /// Hosts pinned to the top of the list.
+    pub favorites: Favorites,
+    /// What the action menu offers.
+    pub actions: Actions,
+    /// A count typed before a nav mode key, like the 5 in `5j`.
+    count: Option<usize>,
+    /// Keys typed so far of a longer binding, like the first `g` of
+    /// `g g`.
+    keys: Vec<Key>,
+    pub keymap: Keymap,
+    /// `/dev/tty`, so stdout is free for `--print`. Tests go without.
+    tty: Option<Tty>,
     matcher: SkimMatcherV2,
 }
 
@@ -40,7 +98,25 @@ pub enum Mode {
     Search,
     Nav,
     Quit,
-    Launch(String),
+    Launch(Launch),
+    /// Filling out the add/edit/clone form.
+    Edit,
+    /// Picking from the action menu.
+    Menu,
+    /// Asking before deleting a host.
+    Delete(String),
+}
+
+/// The order hosts are listed in when there's no search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sort {
+    /// The order they're in the ssh config.
+    Config,
+    /// By alias, ignoring case.
+    Alpha,
+    /// Hosts used most and most recently first, then config order.
+    /// Search results get a boost too.
+    Frecency,
 }
 
 /// Was the input search successful?
@@ -52,82 +128,141 @@ pub enum SearchStatus {
 }
 
 impl TUI {
-    /// Create a new main view and sets up the terminal.
-    pub fn new(config_path: &str) -> io::Result<TUI> {
-        Ok(TUI {
+    /// Create a new main view and sets up the terminal. Hosts come
+    /// from every ssh config in `config_paths` that exists, and with
+    /// `match_exec` their `Match exec` commands are run.
+    pub fn new(config_paths: &[String], match_exec: bool) -> io::Result<TUI> {
+        let mut config = load_ssh_configs_lenient(config_paths)?;
+        config.match_exec = match_exec;
+        let tty = Self::setup_terminal()?;
+        let size = tty.size()?;
+        Ok(TUI::with_config(config, config_paths, Some(tty), size))
+    }
+
+    /// The app for an already loaded `config`, drawing on `tty`, or
+    /// nowhere without one.
+    fn with_config(
+        config: SshConfig,
+        config_paths: &[String],
+        tty: Option<Tty>,
+        size: (u16, u16),
+    ) -> TUI {
+        TUI {
             mode: Mode::Nav,
             status: SearchStatus::Blank,
-            input: String::new(),
+            input: LineEditor::new(),
             selected: 0,
             offset: 0,
-            size: terminal_size()?,
-            hosts: load_ssh_config(config_path)?,
-            stdout: Self::setup_terminal()?,
+            visible: (0..config.hosts.len()).collect(),
+            size,
+            config_paths: config_paths.to_vec(),
+            entries: entries(&config),
+            query: Query::default(),
+            config,
+            form: None,
+            menu: None,
+            message: None,
+            detail: true,
+            sort: Sort::Config,
+            theme: Theme::default(),
+            history: History::default(),
+            favorites: Favorites::default(),
+            actions: default_actions(),
+            count: None,
+            keys: vec![],
+            keymap: Keymap::default(),
+            tty,
             matcher: Default::default(),
-        })
+        }
     }
 
     /// Put the terminal into raw mode, hide the cursor, etc.
-    fn setup_terminal() -> io::Result<RawTerminal<Stdout>> {
-        let mut stdout = io::stdout().into_raw_mode()?;
-        write!(stdout, "{}", ToAlternateScreen)?;
-        write!(stdout, "{}", HideCursor)?;
-        write!(stdout, "{}", ClearAll)?;
-        write!(stdout, "{}", Goto(1, 1))?;
-        stdout.flush()?;
-        Ok(stdout)
+    fn setup_terminal() -> io::Result<Tty> {
+        let mut tty = Tty::open()?;
+        write!(tty, "{}", ToAlternateScreen)?;
+        write!(tty, "{}", HideCursor)?;
+        write!(tty, "{}", ClearAll)?;
+        write!(tty, "{}", Goto(1, 1))?;
+        tty.flush()?;
+        Ok(tty)
     }
 
     /// Restore the terminal to its prior state.
     /// We run this on drop().
     fn cleanup_terminal(&mut self) -> io::Result<()> {
-        self.stdout.suspend_raw_mode()?;
-        write!(self.stdout, "{}", ShowCursor)?;
-        write!(self.stdout, "{}", ToMainScreen)?;
-        self.stdout.flush()?;
+        if let Some(tty) = &mut self.tty {
+            tty.restore()?;
+            write!(tty, "{}", ShowCursor)?;
+            write!(tty, "{}", ToMainScreen)?;
+            tty.flush()?;
+        }
         Ok(())
     }
 
+    /// The settings ssh will use for `alias`.
+    pub fn resolve(&self, alias: &str) -> Host {
+        self.config.resolve(alias)
+    }
+
+    /// Start out searching for `query`.
+    pub fn search_for(&mut self, query: &str) {
+        self.mode = Mode::Search;
+        self.edit_input(|input| {
+            for c in query.chars() {
+                input.insert(c);
+            }
+        });
+    }
+
+    /// Lines of the ssh config we had to skip.
+    pub fn warnings(&self) -> &[ParseError] {
+        &self.config.warnings
+    }
+
     /// Start thread to listen for keyboard events.
     fn event_thread(&self) -> io::Result<Receiver<Key>> {
         let (sender, receiver) = unbounded();
-        thread::spawn(move || loop {
-            sender
-                .send(io::stdin().keys().next().unwrap().unwrap())
-                .unwrap()
+        let tty = get_tty()?;
+        // one iterator for good: termion reads ahead a byte, which a
+        // fresh iterator per key would throw away when typing fast
+        thread::spawn(move || {
+            let mut keys = tty.keys();
+            loop {
+                sender.send(keys.next().unwrap().unwrap()).unwrap()
+            }
         });
         Ok(receiver)
     }
 
     /// Register signal handler. SIGWINCH (resize) only for now.
-    fn signal_thread(&self) -> io::Result<Receiver<Key>> {
+    fn signal_thread(&self) -> io::Result<Receiver<()>> {
         let (sender, receiver) = unbounded();
-        unsafe {
-            signal_hook::register(signal_hook::SIGWINCH, move || {
-                sender.send(Key::F(5)).unwrap()
-            })
-        }?;
+        unsafe { signal_hook::register(signal_hook::SIGWINCH, move || sender.send(()).unwrap()) }?;
 
         Ok(receiver)
     }
 
-    /// Main loop. Returns the host we want to SSH to, if any.
-    pub fn run(&mut self) -> io::Result<Option<String>> {
+    /// Main loop. Returns the host we want to SSH to, and how, if any.
+    pub fn run(&mut self) -> io::Result<Option<Launch>> {
         let ux_rx = self.event_thread()?;
         let signal_rx = self.signal_thread()?;
 
+        self.filter();
         self.update(None)?;
         self.draw()?;
 
         while let Ok(event) = Selector::new()
-            .recv(&ux_rx, |e| e)
-            .recv(&signal_rx, |e| e)
+            .recv(&ux_rx, |e| e.map(Some))
+            .recv(&signal_rx, |e| e.map(|()| None))
             .wait()
         {
-            self.update(Some(event))?;
+            match event {
+                Some(key) => self.update(Some(key))?,
+                None => self.resize()?,
+            }
             match self.mode {
                 Mode::Quit => break,
-                Mode::Launch(ref host) => return Ok(Some(host.clone())),
+                Mode::Launch(ref launch) => return Ok(Some(launch.clone())),
                 _ => self.draw()?,
             }
         }
@@ -140,310 +275,851 @@ impl TUI {
         if event.is_none() {
             return Ok(());
         }
+        self.message = None;
 
-        match event.unwrap() {
-            Key::Ctrl('c') | Key::Esc if self.mode == Mode::Nav => self.mode = Mode::Quit,
-            Key::Char('r') | Key::F(5) if self.mode == Mode::Nav => {
-                self.size = terminal_size()?;
-                // reset offset if the screen grew
-                if self.offset > 0 && self.hosts.len() <= self.size.1 as usize {
-                    self.offset = 0;
-                }
+        let event = event.unwrap();
+        let mode = match self.mode {
+            Mode::Edit => return self.update_form(event),
+            Mode::Delete(_) => return self.update_delete(event),
+            Mode::Menu => return self.update_menu(event),
+            Mode::Search => KeyMode::Search,
+            _ => KeyMode::Nav,
+        };
+
+        if let (KeyMode::Nav, Key::Char(c @ '0'..='9')) = (mode, event) {
+            // a leading 0 isn't a count
+            if self.keys.is_empty() && (c != '0' || self.count.is_some()) {
+                let digit = c.to_digit(10).unwrap_or(0) as usize;
+                let count = self.count.unwrap_or(0).saturating_mul(10);
+                self.count = Some(count.saturating_add(digit));
+                return Ok(());
             }
-            Key::Char(' ') | Key::PageDown => {
-                self.selected += 5;
-                if self.selected > self.hosts.len() - 1 {
-                    self.selected = self.hosts.len() - 1;
-                }
-                self.select(self.selected);
+        }
+        // Esc cancels a half-typed command before it does anything else
+        if event == Key::Esc && (self.count.is_some() || !self.keys.is_empty()) {
+            self.count = None;
+            self.keys.clear();
+            return Ok(());
+        }
+
+        self.keys.push(event);
+        match self.keymap.lookup(mode, &self.keys) {
+            Lookup::Prefix => {}
+            Lookup::Found(action) => {
+                self.keys.clear();
+                let count = self.count.take();
+                self.perform(action, count)?;
             }
-            Key::Char('-') | Key::PageUp => {
-                if self.selected > 5 {
-                    self.selected -= 5;
-                } else {
-                    self.selected = 0;
+            Lookup::Unbound if self.keys.len() > 1 => {
+                // the start of a sequence that didn't pan out: type it
+                // if we're searching, then try the last key on its own
+                self.keys.pop();
+                for key in std::mem::take(&mut self.keys) {
+                    self.type_key(key);
                 }
-                self.select(self.selected);
+                return self.update(Some(event));
             }
-            Key::Up | Key::Ctrl('p') => self.select_prev(),
-            Key::Down | Key::Ctrl('n') => self.select_next(),
-            Key::Char('\n') => {
-                if self.mode == Mode::Search && self.status == SearchStatus::Missed {
-                    // do nothing on a search that doesn't match
-                } else if let Some(host) = self.hosts.iter().nth(self.selected) {
-                    self.mode = Mode::Launch(host.0.clone());
-                } else {
-                    return Err(io::Error::new(io::ErrorKind::Other, "can't find host"));
-                }
+            Lookup::Unbound => {
+                self.keys.clear();
+                self.count = None;
+                self.type_key(event);
             }
-            event if self.mode == Mode::Nav => match event {
-                Key::Char('q') => self.mode = Mode::Quit,
-                Key::Char('i') | Key::Char('s') | Key::Char('/') | Key::Char('f') => {
-                    self.status = SearchStatus::Blank;
-                    self.mode = Mode::Search
-                }
-                _ => {}
-            },
-            event if self.mode == Mode::Search => self.update_input(event),
-            _ => {}
         }
 
         Ok(())
     }
 
-    /// Search mode-specific keybindings.
-    fn update_input(&mut self, event: Key) {
-        match event {
-            Key::Ctrl('c') | Key::Esc => {
+    /// Do what a key is bound to, `count` times if it makes sense.
+    fn perform(&mut self, action: Action, count: Option<usize>) -> io::Result<()> {
+        let has_hosts = !self.config.hosts.is_empty();
+        // nothing to act on when a search doesn't match
+        let missed = self.mode == Mode::Search && self.status == SearchStatus::Missed;
+        if let Some(i) = self.nav().target(action, count, self.mode == Mode::Nav) {
+            self.select(i);
+            return Ok(());
+        }
+
+        match action {
+            Action::Quit => self.mode = Mode::Quit,
+            Action::Refresh => self.resize()?,
+            Action::Search => {
                 self.status = SearchStatus::Blank;
+                self.mode = Mode::Search
+            }
+            Action::Cancel => {
                 if self.input.is_empty() {
                     self.mode = Mode::Nav;
                 } else {
                     self.input.clear();
                 }
+                self.refilter();
+                self.status = SearchStatus::Blank;
             }
-            Key::Backspace => {
-                if !self.input.is_empty() {
-                    self.input.truncate(self.input.len() - 1);
-                    self.select_search_host();
+            Action::Connect => {
+                // a search that doesn't match has nothing to connect to
+                if let (false, Some(host)) = (missed, self.selected_host()) {
+                    self.mode = Mode::Launch(Launch::connect(&host.name));
                 }
-                if self.input.is_empty() {
-                    self.status = SearchStatus::Blank;
+            }
+            Action::ToggleDetail => self.detail = !self.detail,
+            Action::AddHost => self.open_form(FormKind::Add),
+            Action::EditHost if has_hosts => {
+                self.open_form(FormKind::Edit(self.selected_name().to_string()))
+            }
+            Action::CloneHost if has_hosts => {
+                self.open_form(FormKind::Clone(self.selected_name().to_string()))
+            }
+            Action::DeleteHost if has_hosts => {
+                self.mode = Mode::Delete(self.selected_name().to_string())
+            }
+            Action::ToggleFavorite => self.toggle_favorite(),
+            Action::ActionMenu if has_hosts && !missed => {
+                if self.actions.is_empty() {
+                    self.message = Some("No actions set up".into());
+                } else {
+                    self.menu = Some(Menu::new(self.selected_name()));
+                    self.mode = Mode::Menu;
                 }
             }
-            Key::Char(c) => {
-                self.input.push(c);
-                self.select_search_host();
+            Action::Edit(edit) if self.mode == Mode::Search => {
+                self.edit_input(|input| input.edit(edit))
             }
             _ => {}
         }
+        Ok(())
     }
 
-    /// Select a host by index.
-    fn select(&mut self, i: usize) {
-        self.selected = i;
-        self.status = SearchStatus::Found;
-        if !self.is_visible(self.selected) {
-            let rows = self.size.1 as usize - 2;
-            if self.selected == 0 {
-                self.offset = 0;
-            } else if self.selected < self.offset {
-                self.offset = self.selected;
-            } else if self.selected > rows {
-                self.offset = self.selected - rows;
-            }
+    /// Re-read the terminal size after it changes.
+    fn resize(&mut self) -> io::Result<()> {
+        if let Some(tty) = &self.tty {
+            self.size = tty.size()?;
+        }
+        // reset offset if the screen grew
+        if self.offset > 0 && self.visible.len() <= self.size.1 as usize {
+            self.offset = 0;
         }
+        Ok(())
     }
 
-    /// Is the host at the given index visible on screen?
-    fn is_visible(&self, i: usize) -> bool {
-        i >= self.offset && i < self.offset + (self.size.1 as usize - 1)
+    /// Show the add/edit/clone form.
+    fn open_form(&mut self, kind: FormKind) {
+        self.form = Some(Form::new(kind, &self.config));
+        self.mode = Mode::Edit;
     }
 
-    /// Select the previous host (up). If we're in search mode, only
-    /// selects a matching host.
-    fn select_prev(&mut self) {
-        if self.mode == Mode::Search && !self.input.is_empty() {
-            let mut i = self.selected;
-            let hosts = self.hosts.iter().map(|(h, _)| h).collect::<Vec<_>>();
-            while i > 0 {
-                i -= 1;
-                if let Some(host) = hosts.get(i) {
-                    if self.host_matches(host, &self.input) {
-                        self.select(i);
-                        return;
-                    }
-                }
+    /// Form keybindings. Saving writes the config file and reloads.
+    fn update_form(&mut self, event: Key) -> io::Result<()> {
+        let form = match self.form.as_mut() {
+            Some(form) => form,
+            None => {
+                self.mode = Mode::Nav;
+                return Ok(());
             }
-        } else {
-            if self.selected == 0 {
-                self.selected = self.hosts.len() - 1;
-            } else {
-                self.selected -= 1;
+        };
+
+        match form.update(event) {
+            FormAction::Continue => {}
+            FormAction::Cancel => {
+                self.form = None;
+                self.mode = Mode::Nav;
             }
-        }
-        if !self.is_visible(self.selected) {
-            let rows = self.size.1 as usize - 2;
-            if self.selected > self.offset && self.selected > rows {
-                self.offset = self.selected - rows;
-            } else {
-                self.offset = self.selected;
+            FormAction::Save => {
+                // errors stay on the form so they can be fixed
+                if let Ok(alias) = form.save(&self.config_paths[0], &self.config) {
+                    self.form = None;
+                    self.mode = Mode::Nav;
+                    self.reload(Some(&alias))?;
+                    self.message = Some(format!("Saved {}", alias));
+                }
             }
         }
+        Ok(())
     }
 
-    /// Select the previous host (up). If we're in search mode, only
-    /// selects a matching host.
-    fn select_next(&mut self) {
-        if self.mode == Mode::Search && !self.input.is_empty() {
-            let mut i = self.selected;
-            let hosts = self.hosts.iter().map(|(h, _)| h).collect::<Vec<_>>();
-            while i < hosts.len() {
-                i += 1;
-                if let Some(host) = hosts.get(i) {
-                    if self.host_matches(host, &self.input) {
-                        self.select(i);
-                        return;
-                    }
-                }
-            }
+    /// Action menu keys. Leaving it goes back to the mode we came from.
+    fn update_menu(&mut self, event: Key) -> io::Result<()> {
+        let back = if self.input.is_empty() {
+            Mode::Nav
         } else {
-            if self.selected >= self.hosts.len() - 1 {
-                self.selected = 0;
-            } else {
-                self.selected += 1;
+            Mode::Search
+        };
+        let menu = match self.menu.as_mut() {
+            Some(menu) => menu,
+            None => {
+                self.mode = back;
+                return Ok(());
             }
-        }
-        if !self.is_visible(self.selected) {
-            let rows = self.size.1 as usize - 2;
-            if self.selected == 0 {
-                self.offset = 0;
-            } else if self.selected > rows {
-                self.offset = self.selected - rows;
+        };
+
+        match menu.update(event, &self.actions) {
+            MenuAction::Continue => {}
+            MenuAction::Cancel => {
+                self.menu = None;
+                self.mode = back;
+            }
+            MenuAction::Launch(launch) => {
+                self.menu = None;
+                self.mode = Mode::Launch(launch);
             }
         }
+        Ok(())
     }
 
-    /// Checks the current self.input against hostnames to find and
-    /// select a match.
-    fn select_search_host(&mut self) {
-        for (i, (host, _)) in self.hosts.iter().enumerate() {
-            if self.host_matches(host, &self.input) {
-                self.select(i);
-                return;
+    /// Delete confirmation: `y` deletes, anything else backs out.
+    fn update_delete(&mut self, event: Key) -> io::Result<()> {
+        let alias = match &self.mode {
+            Mode::Delete(alias) => alias.clone(),
+            _ => return Ok(()),
+        };
+        self.mode = Mode::Nav;
+        if event != Key::Char('y') && event != Key::Char('Y') {
+            return Ok(());
+        }
+
+        let file = match self.config.hosts.get(&alias) {
+            Some(host) => host.file.clone(),
+            None => return Ok(()),
+        };
+        let deleted = Document::load(&file).and_then(|mut doc| {
+            doc.remove_host(&alias)?;
+            doc.save(&file)
+        });
+        match deleted {
+            Ok(()) => {
+                self.reload(None)?;
+                self.message = Some(format!("Deleted {}", alias));
             }
+            Err(e) => self.message = Some(format!("Can't delete {}: {}", alias, e)),
         }
+        Ok(())
+    }
 
-        self.status = SearchStatus::Missed;
+    /// Re-read the ssh config after we've changed it, selecting
+    /// `alias` if it's given or staying put otherwise.
+    fn reload(&mut self, alias: Option<&str>) -> io::Result<()> {
+        let match_exec = self.config.match_exec;
+        self.config = load_ssh_configs_lenient(&self.config_paths)?;
+        self.config.match_exec = match_exec;
+        self.entries = entries(&self.config);
+        self.filter();
+        let selected = alias
+            .and_then(|alias| self.config.hosts.get_full(alias))
+            .and_then(|(i, _, _)| self.visible.iter().position(|&v| v == i))
+            .unwrap_or(self.selected)
+            .min(self.visible.len().saturating_sub(1));
+        self.select(selected);
+        Ok(())
     }
 
-    /// Does a given host match our search string? Just a prefix
-    /// match, for now.
-    fn host_matches(&self, host: &str, search: &str) -> bool {
-        self.matcher.fuzzy_match(host, search).is_some()
+    /// Type an unbound key into the search prompt. Other keys, and
+    /// any key in nav mode, are ignored.
+    fn type_key(&mut self, key: Key) {
+        if let (Mode::Search, Key::Char(c)) = (&self.mode, key) {
+            self.edit_input(|input| {
+                input.insert(c);
+            });
+        }
     }
 
-    /// The name of the currently selected host pattern.
-    fn selected_name(&self) -> &str {
-        if let Some((_, (name, _))) = self
+    /// Change the search input, filtering again if the text changed.
+    fn edit_input(&mut self, edit: impl FnOnce(&mut LineEditor)) {
+        let before = self.input.as_str().to_string();
+        edit(&mut self.input);
+        if self.input.as_str() != before {
+            self.select_search_host();
+        }
+        if self.input.is_empty() {
+            self.status = SearchStatus::Blank;
+        }
+    }
+
+    /// Select a host by index. With nothing listed there's nothing to
+    /// select, and the status stays as it is.
+    fn select(&mut self, i: usize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        self.selected = i;
+        self.status = SearchStatus::Found;
+        self.offset = self.nav().scroll(i);
+    }
+
+    /// Where the selection is, for working out where it moves to.
+    fn nav(&self) -> Nav {
+        Nav {
+            len: self.visible.len(),
+            height: self.list_height(),
+            selected: self.selected,
+            offset: self.offset,
+        }
+    }
+
+    /// How many hosts fit on screen above the status bar.
+    fn list_height(&self) -> usize {
+        (self.size.1 as usize).saturating_sub(1).max(1)
+    }
+
+    /// Rebuild `visible` from the search input: every host in `sort`
+    /// order when there isn't any, otherwise just the hosts that match,
+    /// best first. Ties keep their `sort` order.
+    fn filter(&mut self) {
+        self.query = match self.mode {
+            Mode::Search => Query::parse(self.input.as_str()),
+            _ => Query::default(),
+        };
+        let frecency = self.frecency();
+        let mut order = (0..self.entries.len()).collect::<Vec<_>>();
+        match self.sort {
+            Sort::Config => {}
+            Sort::Alpha => order.sort_by_cached_key(|&i| self.entries[i].alias.to_lowercase()),
+            Sort::Frecency => order.sort_by_key(|&i| Reverse(frecency[i])),
+        }
+        if self.query.is_empty() {
+            // favorites go on top, in the same order
+            order.sort_by_key(|&i| !self.is_favorite(i));
+            self.visible = order;
+            return;
+        }
+
+        let boosts = frecency.into_iter().map(history::boost).collect::<Vec<_>>();
+        self.visible = rank(&self.matcher, &self.entries, &order, &boosts, &self.query);
+    }
+
+    /// Is the host at `index` in the config pinned?
+    fn is_favorite(&self, index: usize) -> bool {
+        self.config
             .hosts
+            .get_index(index)
+            .is_some_and(|(_, host)| self.favorites.contains(host))
+    }
+
+    /// Pin the selected host, or unpin it.
+    fn toggle_favorite(&mut self) {
+        let host = match self.selected_host() {
+            Some(host) => host,
+            None => return,
+        };
+        let alias = host.name.clone();
+        if is_annotated(host) {
+            let file = host.file.display();
+            self.message = Some(format!("{} is pinned by a comment in {}", alias, file));
+            return;
+        }
+
+        self.message = Some(match self.favorites.toggle(&alias) {
+            Ok(true) => format!("Pinned {}", alias),
+            Ok(false) => format!("Unpinned {}", alias),
+            Err(e) => format!("Can't save favorites: {}", e),
+        });
+        self.refilter();
+    }
+
+    /// Each host's frecency score, in config order. They're all 0
+    /// unless we're sorting by frecency.
+    fn frecency(&self) -> Vec<i64> {
+        if self.sort != Sort::Frecency {
+            return vec![0; self.entries.len()];
+        }
+        let scores = self.history.scores(history::now());
+        self.entries
             .iter()
-            .enumerate()
-            .find(|(i, _)| *i == self.selected)
-        {
-            name
-        } else {
-            "shy"
+            .map(|entry| scores.get(entry.alias.as_str()).cloned().unwrap_or(0))
+            .collect()
+    }
+
+    /// Filter again, keeping the same host selected if it's still on
+    /// screen.
+    fn refilter(&mut self) {
+        let host = self.visible.get(self.selected).cloned();
+        self.filter();
+        let selected = host
+            .and_then(|host| self.visible.iter().position(|&i| i == host))
+            .unwrap_or(0);
+        self.offset = 0;
+        self.select(selected);
+    }
+
+    /// Filter the list down to hosts matching self.input and select the
+    /// best one.
+    fn select_search_host(&mut self) {
+        self.filter();
+        self.offset = 0;
+        self.select(0);
+        if self.visible.is_empty() {
+            self.status = SearchStatus::Missed;
+        }
+    }
+
+    /// The name of the currently selected host pattern.
+    fn selected_name(&self) -> &str {
+        match self.selected_host() {
+            Some(host) => &host.name,
+            None => "shy",
         }
     }
 
     /// The hostname of the currently selected host pattern. The two
     /// might be different.
     fn selected_hostname(&self) -> &str {
-        if let Some((_, (_, hostname))) = self
-            .hosts
-            .iter()
-            .enumerate()
-            .find(|(i, _)| *i == self.selected)
-        {
-            hostname
-        } else {
-            "shy"
+        match self.selected_host() {
+            Some(host) => host.hostname(),
+            None => "shy",
+        }
+    }
+
+    /// The currently selected host, if there is one.
+    fn selected_host(&self) -> Option<&Host> {
+        let i = *self.visible.get(self.selected)?;
+        self.config.hosts.get_index(i).map(|(_, host)| host)
+    }
+
+    /// Is the detail pane on screen? It needs a wide enough terminal.
+    fn showing_detail(&self) -> bool {
+        self.detail && self.size.0 >= DETAIL_MIN_COLS && !self.visible.is_empty()
+    }
+
+    /// How many columns the host list gets.
+    fn list_width(&self) -> usize {
+        let cols = self.size.0 as usize;
+        if !self.showing_detail() {
+            return cols;
         }
+        let longest = self.config.hosts.keys().map(|h| h.chars().count()).max();
+        (longest.unwrap_or(0) + 3).clamp(20, cols / 2)
     }
 
-    /// (bg, fg) colors for the prompt
-    fn prompt_colors(&self) -> (&str, &str) {
+    /// The prompt's style, which shows how the search is going.
+    fn prompt_style(&self) -> Style {
         match self.status {
-            SearchStatus::Blank => (color::WhiteBG.as_ref(), color::Black.as_ref()),
-            SearchStatus::Found => (color::GreenBG.as_ref(), color::Black.as_ref()),
-            SearchStatus::Missed => (color::RedBG.as_ref(), color::White.as_ref()),
+            SearchStatus::Blank => self.theme.prompt,
+            SearchStatus::Found => self.theme.prompt_found,
+            SearchStatus::Missed => self.theme.prompt_missed,
         }
     }
 
     /// Draw the ui
     pub fn draw(&self) -> io::Result<()> {
-        let (_cols, rows) = self.size;
-        let mut stdout = io::stdout();
+        let (cols, rows) = self.size;
+        let mut tty = match &self.tty {
+            Some(tty) => BufWriter::new(tty.file()),
+            None => return Ok(()),
+        };
+        write!(tty, "{}", HideCursor)?;
 
-        if self.mode == Mode::Search {
-            let (bg, fg) = self.prompt_colors();
+        if let (Mode::Edit, Some(form)) = (&self.mode, &self.form) {
+            return self.draw_form(&mut tty, form);
+        }
+        if let (Mode::Menu, Some(menu)) = (&self.mode, &self.menu) {
+            return self.draw_menu(&mut tty, menu);
+        }
+
+        let theme = &self.theme;
+        if let Mode::Delete(alias) = &self.mode {
+            write!(
+                tty,
+                "{}{}{}{}{}",
+                ClearAll,
+                Goto(1, rows),
+                theme.error.code(),
+                ClearLine,
+                theme.error.paint(&format!("Delete {}? (y/n)", alias))
+            )?;
+        } else if let Some(message) = &self.message {
+            write!(
+                tty,
+                "{}{}{}{}{}",
+                ClearAll,
+                Goto(1, rows),
+                theme.status.code(),
+                ClearLine,
+                theme.status.paint(message)
+            )?;
+        } else if self.mode == Mode::Search {
             write!(
-                stdout,
-                "{}{}{}{}{}{}{}{}",
+                tty,
+                "{}{}{}{}>> {}",
                 ClearAll,
                 Goto(1, rows),
-                bg,
-                fg,
+                self.prompt_style().code(),
                 ClearLine,
-                ">> ",
-                self.highlight_matches()?,
-                color!(Reset),
+                self.input.as_str(),
             )?;
+
+            // say what matched, so `10.0` finding a host makes sense
+            let prompt = self.input.as_str().width() + 4;
+            if let Some(found) = self.selected_match() {
+                let label = format!("{}: ", found.field);
+                let room = (cols as usize).saturating_sub(prompt + label.len() + 1);
+                let value = fit(&found.value, room);
+                let width = label.len() + value.chars().count() + 1;
+                if room > 0 {
+                    write!(
+                        tty,
+                        "{}{}{}",
+                        Goto((cols as usize - width) as u16 + 1, rows),
+                        label,
+                        highlight(value, &found.indices, "\x1b[1m", "\x1b[22m")
+                    )?;
+                }
+            }
+            write!(tty, "{}", color!(Reset))?;
         } else {
             write!(
-                stdout,
-                "{}{}{}{}{}{}",
+                tty,
+                "{}{}{}{}{}",
                 ClearAll,
                 Goto(1, rows),
-                color!(MagentaBG),
-                color!(Yellow),
+                theme.status.code(),
                 ClearLine,
-                color_string!(self.selected_hostname(), MagentaBG, Yellow, Bold)
+                theme.status.paint(self.selected_hostname())
             )?;
+
+            // let people know we skipped part of their config
+            let warnings = self.warnings().len();
+            if warnings > 0 {
+                let msg = format!(
+                    "{} config warning{} ",
+                    warnings,
+                    if warnings == 1 { "" } else { "s" }
+                );
+                write!(
+                    tty,
+                    "{}{}",
+                    Goto(cols.saturating_sub(msg.len() as u16) + 1, rows),
+                    theme.status.paint(&msg)
+                )?;
+            }
         }
 
-        let mut row = 1;
-        for (i, (host, _config)) in self.hosts.iter().enumerate().skip(self.offset) {
+        let list_width = self.list_width();
+        for (row, (i, &index)) in (1..).zip(self.visible.iter().enumerate().skip(self.offset)) {
             if i >= self.offset + (rows as usize - 1) {
                 break;
             }
+            let (alias, host) = self.config.hosts.get_index(index).unwrap();
+            let pin = if self.favorites.contains(host) {
+                theme.pinned.paint("*")
+            } else {
+                " ".into()
+            };
+            let host = fit(alias, list_width - 2);
+            let matched = self
+                .found(index)
+                .map(|found| found.alias_indices)
+                .unwrap_or_default();
 
             write!(
-                stdout,
+                tty,
                 "{}{}",
                 Goto(1, row),
                 if i == self.selected {
-                    format!("> {}", color_string!(host, Yellow, Bold))
+                    format!(
+                        ">{}{}",
+                        pin,
+                        paint(host, &matched, theme.selected, theme.selected_match)
+                    )
                 } else {
-                    format!("  {}", color_string!(host, White))
+                    format!(
+                        " {}{}",
+                        pin,
+                        paint(host, &matched, theme.host, theme.matched)
+                    )
                 }
             )?;
-            row += 1;
         }
 
-        stdout.flush()?;
+        if self.showing_detail() {
+            self.draw_detail(&mut tty, list_width as u16 + 1)?;
+        }
+
+        if self.mode == Mode::Search {
+            let column = self.input.cursor_column() + 4;
+            write!(tty, "{}{}", Goto(column as u16, rows), ShowCursor)?;
+        }
+
+        tty.flush()?;
         Ok(())
     }
 
-    /// Highlight (embolden) the matching letters in a host, which may
-    /// not be consecutive since we use fuzzy finding.
-    fn highlight_matches(&self) -> io::Result<Cow<str>> {
-        if self.input.is_empty() {
-            return Ok(Cow::from(""));
+    /// Draw the selected host's effective settings, and where they came
+    /// from, in a pane starting at column `left`.
+    fn draw_detail(&self, tty: &mut impl Write, left: u16) -> io::Result<()> {
+        let (cols, rows) = self.size;
+        let width = (cols - left) as usize - 2;
+        let alias = self.selected_name();
+        let settings = self.config.explain(alias);
+        let theme = &self.theme;
+
+        for row in 1..rows {
+            write!(tty, "{}{}", Goto(left, row), theme.dim.paint("│"))?;
         }
+        write!(
+            tty,
+            "{}{}",
+            Goto(left + 2, 1),
+            theme.title.paint(fit(alias, width))
+        )?;
+
+        let mut lines = vec![];
+        for key in DETAIL_KEYS {
+            let found = settings
+                .iter()
+                .filter(|s| s.key.eq_ignore_ascii_case(key))
+                .collect::<Vec<_>>();
+            if key == &"ProxyJump" && !found.is_empty() {
+                // one line for the whole chain of jump hosts
+                let chain = found[0].value.split(',').collect::<Vec<_>>().join(" → ");
+                lines.push((key.to_string(), chain, origin(found[0])));
+            } else if !found.is_empty() {
+                for setting in found {
+                    lines.push((key.to_string(), setting.value.clone(), origin(setting)));
+                }
+            } else if let Some(default) = match *key {
+                "User" => Some(local_user()),
+                "HostName" => Some(alias.to_string()),
+                "Port" => Some("22".to_string()),
+                _ => None,
+            } {
+                lines.push((key.to_string(), default, "default".to_string()));
+            }
+        }
+        for setting in &settings {
+            if !DETAIL_KEYS
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(&setting.key))
+            {
+                lines.push((setting.key.clone(), setting.value.clone(), origin(setting)));
+            }
+        }
+
+        for (row, (key, value, origin)) in (3..rows).zip(lines) {
+            let value = fit(&value, width.saturating_sub(20));
+            let room = width.saturating_sub(20 + value.chars().count());
+            write!(
+                tty,
+                "{}{}{}",
+                Goto(left + 2, row),
+                theme.label.paint(&format!("{:<20}", fit(&key, 19))),
+                value
+            )?;
+            if room > 4 {
+                write!(
+                    tty,
+                    "{}",
+                    theme.dim.paint(fit(&format!("  {}", origin), room))
+                )?;
+            }
+        }
+
+        Ok(())
+    }
 
-        if self.status == SearchStatus::Missed {
-            return Ok(Cow::from(&self.input));
+    /// Draw the add/edit/clone form over the whole screen.
+    fn draw_form(&self, tty: &mut impl Write, form: &Form) -> io::Result<()> {
+        let (_cols, rows) = self.size;
+        let theme = &self.theme;
+
+        write!(
+            tty,
+            "{}{}{}",
+            ClearAll,
+            Goto(1, 1),
+            theme.title.paint(&form.title())
+        )?;
+        for (row, (i, label)) in (3..).zip(Form::labels().enumerate()) {
+            let value = &form.values[i];
+            write!(
+                tty,
+                "{}{}",
+                Goto(1, row),
+                if i == form.focus {
+                    format!(
+                        "> {}{}",
+                        theme.title.paint(&format!("{:<13}", label)),
+                        theme.selected.paint(&format!("{}_", value))
+                    )
+                } else {
+                    format!("  {}{}", theme.host.paint(&format!("{:<13}", label)), value)
+                }
+            )?;
         }
 
-        let mut out = String::new();
-        let mut host = self.selected_name();
-        for c in self.input.chars() {
-            if let Some(idx) = host.find(c) {
-                if idx > 0 {
-                    out.push_str(&host[..idx]);
+        let (status, style) = match &form.error {
+            Some(err) => (err.clone(), theme.error),
+            None => (
+                "Enter: save  Tab: next field  Esc: cancel".to_string(),
+                theme.status,
+            ),
+        };
+        write!(
+            tty,
+            "{}{}{}{}{}",
+            Goto(1, rows),
+            style.code(),
+            ClearLine,
+            status,
+            color!(Reset)
+        )?;
+
+        tty.flush()?;
+        Ok(())
+    }
+
+    /// Draw the action menu, or the picked action's questions, over
+    /// the whole screen.
+    fn draw_menu(&self, tty: &mut impl Write, menu: &Menu) -> io::Result<()> {
+        let (cols, rows) = self.size;
+        let theme = &self.theme;
+        let width = cols as usize;
+
+        let title = match &menu.asking {
+            Some(answers) => format!("{} on {}", answers.action, menu.alias),
+            None => format!("Run on {}", menu.alias),
+        };
+        write!(
+            tty,
+            "{}{}{}",
+            ClearAll,
+            Goto(1, 1),
+            theme.title.paint(fit(&title, width))
+        )?;
+
+        let (status, style) = match &menu.asking {
+            None => {
+                let name_width = self.actions.keys().map(|n| n.width()).max().unwrap_or(0) + 2;
+                for (row, (i, (name, launcher))) in (3..rows).zip(self.actions.iter().enumerate()) {
+                    let number = if i < 9 {
+                        format!("{} ", i + 1)
+                    } else {
+                        "  ".into()
+                    };
+                    let name = format!("{:<1$}", name, name_width);
+                    let template = fit(&launcher.to_string(), width.saturating_sub(name_width + 4))
+                        .to_string();
+                    write!(
+                        tty,
+                        "{}{}",
+                        Goto(1, row),
+                        if i == menu.selected {
+                            format!(">{}{}", number, theme.selected.paint(&name))
+                        } else {
+                            format!(" {}{}", number, theme.host.paint(&name))
+                        }
+                    )?;
+                    write!(tty, "{}", theme.dim.paint(&template))?;
                 }
-                out.push_str("\x1b[1m");
-                out.push(c);
-                out.push_str("\x1b[22m");
-                host = &host[idx + 1..];
+                ("Enter: run  Esc: cancel".to_string(), theme.status)
             }
+            Some(answers) => {
+                if let Some(launcher) = self.actions.get(&answers.action) {
+                    write!(
+                        tty,
+                        "{}{}",
+                        Goto(1, 2),
+                        theme.dim.paint(fit(&launcher.to_string(), width))
+                    )?;
+                }
+                let label_width = answers
+                    .questions
+                    .iter()
+                    .map(|q| q.label.width())
+                    .max()
+                    .unwrap_or(0)
+                    + 2;
+                for (row, (i, question)) in (4..rows).zip(answers.questions.iter().enumerate()) {
+                    let label = format!("{:<1$}", question.label, label_width);
+                    let value = &answers.values[i];
+                    write!(
+                        tty,
+                        "{}{}",
+                        Goto(1, row),
+                        if i == answers.focus {
+                            format!(
+                                "> {}{}",
+                                theme.title.paint(&label),
+                                theme.selected.paint(&format!("{}_", value))
+                            )
+                        } else {
+                            format!("  {}{}", theme.host.paint(&label), value)
+                        }
+                    )?;
+                }
+                match &answers.error {
+                    Some(err) => (err.clone(), theme.error),
+                    None => (
+                        "Enter: run  Tab: next field  Esc: back".to_string(),
+                        theme.status,
+                    ),
+                }
+            }
+        };
+
+        write!(
+            tty,
+            "{}{}{}{}{}",
+            Goto(1, rows),
+            style.code(),
+            ClearLine,
+            status,
+            color!(Reset)
+        )?;
+
+        tty.flush()?;
+        Ok(())
+    }
+
+    /// How the host at `index` in the config matched the search, if
+    /// we're searching.
+    fn found(&self, index: usize) -> Option<Match> {
+        if self.mode != Mode::Search || self.query.is_empty() {
+            return None;
         }
-        if !host.is_empty() {
-            out.push_str(host);
+        self.query.matches(&self.matcher, self.entries.get(index)?)
+    }
+
+    /// How the selected host matched the search.
+    fn selected_match(&self) -> Option<Match> {
+        self.found(*self.visible.get(self.selected)?)
+    }
+}
+
+/// `text` in `style`, with the chars at `indices` in `matched` too.
+fn paint(text: &str, indices: &[usize], style: Style, matched: Style) -> String {
+    // a reset in the middle would lose the row's own style
+    let off = format!("{}{}", color::Reset, style.code());
+    style.paint(&highlight(text, indices, &matched.code(), &off))
+}
+
+/// Wrap the chars of `text` at the given char `indices` in the `on`
+/// and `off` escape codes.
+fn highlight(text: &str, indices: &[usize], on: &str, off: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if indices.contains(&i) {
+            out.push_str(on);
+            out.push(c);
+            out.push_str(off);
+        } else {
+            out.push(c);
         }
-        Ok(Cow::from(out))
+    }
+    out
+}
+
+/// Where a setting came from, for the detail pane: the stanza, and
+/// the file too if it has one.
+fn origin(setting: &Setting) -> String {
+    match setting.stanza.file.file_name() {
+        Some(file) => format!("{} ({})", setting.stanza.condition, file.to_string_lossy()),
+        None => setting.stanza.condition.to_string(),
+    }
+}
+
+/// The start of `text` that fits in `width` columns.
+fn fit(text: &str, width: usize) -> &str {
+    match text.char_indices().nth(width) {
+        Some((i, _)) => &text[..i],
+        None => text,
     }
 }
 
@@ -453,3 +1129,101 @@ impl Drop for TUI {
         let _ = self.cleanup_terminal();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, fuzzy_matcher::FuzzyMatcher};
+
+    /// The app for tests/test_config, on a 80x10 screen it never draws.
+    fn app() -> TUI {
+        let paths = vec!["./tests/test_config".to_string()];
+        let config = load_ssh_configs_lenient(&paths).unwrap();
+        TUI::with_config(config, &paths, None, (80, 10))
+    }
+
+    fn press(app: &mut TUI, keys: &[Key]) {
+        for key in keys {
+            app.update(Some(*key)).unwrap();
+        }
+    }
+
+    fn type_keys(app: &mut TUI, text: &str) {
+        press(app, &text.chars().map(Key::Char).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sequences() {
+        let mut app = app();
+        type_keys(&mut app, "G");
+        assert_eq!(app.visible.len() - 1, app.selected);
+        type_keys(&mut app, "gg");
+        assert_eq!(0, app.selected);
+        // "g j" isn't bound, so that's g on its own and then j
+        type_keys(&mut app, "gj");
+        assert_eq!(1, app.selected);
+        assert!(app.keys.is_empty());
+    }
+
+    #[test]
+    fn test_sequences_with_counts() {
+        let mut app = app();
+        type_keys(&mut app, "3G");
+        assert_eq!(2, app.selected);
+        // the count carries over to the key that's tried again
+        type_keys(&mut app, "2gj");
+        assert_eq!(4, app.selected);
+        assert_eq!(None, app.count);
+        type_keys(&mut app, "9gg");
+        assert_eq!(8, app.selected);
+        // Esc drops a half-typed count and sequence
+        type_keys(&mut app, "5g");
+        press(&mut app, &[Key::Esc]);
+        assert_eq!(None, app.count);
+        assert!(app.keys.is_empty());
+        type_keys(&mut app, "j");
+        assert_eq!(9, app.selected);
+    }
+
+    #[test]
+    fn test_sequences_while_searching() {
+        let mut app = app();
+        app.keymap.bind(KeyMode::Search, "x y", "cancel").unwrap();
+        app.search_for("dock");
+        // x waits for y; anything else types them both
+        type_keys(&mut app, "x");
+        assert_eq!("dock", app.input.as_str());
+        type_keys(&mut app, "z");
+        assert_eq!("dockxz", app.input.as_str());
+        type_keys(&mut app, "xx");
+        assert_eq!("dockxzx", app.input.as_str());
+        assert_eq!(vec![Key::Char('x')], app.keys);
+        type_keys(&mut app, "y");
+        assert_eq!("", app.input.as_str());
+        assert_eq!(Mode::Search, app.mode);
+    }
+
+    #[test]
+    fn test_missed_search() {
+        let mut app = app();
+        app.search_for("zzqqxx");
+        assert!(app.visible.is_empty());
+        press(
+            &mut app,
+            &[Key::PageDown, Key::PageUp, Key::Down, Key::Char('\n')],
+        );
+        assert_eq!(Mode::Search, app.mode);
+        assert!(app.status == SearchStatus::Missed);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let matcher = SkimMatcherV2::default();
+        let (_, indices) = matcher.fuzzy_indices("bücher.de", "chd").unwrap();
+        assert_eq!(
+            "bü[c][h]er.[d]e",
+            highlight("bücher.de", &indices, "[", "]")
+        );
+        assert_eq!("名前", highlight("名前", &[], "[", "]"));
+        assert_eq!("[名]前", highlight("名前", &[0], "[", "]"));
+    }
+}